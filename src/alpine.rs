@@ -0,0 +1,369 @@
+//! Alpine.js, which is often used alongside htmx for client side state
+//!
+//! Directives are written as `x-on:click.outside`, or with the `@click` and `:class` shorthands for
+//! `x-on` and `x-bind`, followed by modifiers separated by dots
+
+use std::fmt::Write;
+
+use lsp_types::{CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind};
+use phf::phf_map;
+
+use crate::{textstore::FileData, values::completion};
+
+pub static ATTRIBUTES: &[&str] = &[
+    "x-data",
+    "x-init",
+    "x-show",
+    "x-bind",
+    "x-on",
+    "x-text",
+    "x-html",
+    "x-model",
+    "x-modelable",
+    "x-for",
+    "x-transition",
+    "x-effect",
+    "x-ignore",
+    "x-ref",
+    "x-cloak",
+    "x-teleport",
+    "x-if",
+    "x-id",
+];
+
+#[allow(clippy::needless_raw_string_hashes)]
+pub static DESCRIPTIONS: phf::Map<&'static str, &'static str> = phf_map! {
+    "x-data" =>
+r###"declares a new Alpine component and its reactive data
+
+```html
+<div x-data="{ open: false }">
+  <button @click="open = !open">Toggle</button>
+  <div x-show="open">Content...</div>
+</div>
+```
+
+The data is available to the element and all of its children.
+
+[Documentation](https://alpinejs.dev/directives/data)"###,
+    "x-init" =>
+r###"runs an expression when the element is initialized
+
+```html
+<div x-init="date = new Date()"></div>
+```
+
+[Documentation](https://alpinejs.dev/directives/init)"###,
+    "x-show" =>
+r###"shows or hides the element by toggling `display: none`, depending on an expression
+
+```html
+<div x-show="open">Contents...</div>
+```
+
+[Documentation](https://alpinejs.dev/directives/show)"###,
+    "x-bind" =>
+r###"sets an attribute of the element to the result of an expression, also written `:attribute`
+
+```html
+<button :class="open ? '' : 'hidden'">...</button>
+```
+
+[Documentation](https://alpinejs.dev/directives/bind)"###,
+    "x-on" =>
+r###"runs an expression when an event is dispatched on the element, also written `@event`
+
+```html
+<button @click.outside="open = false">...</button>
+```
+
+Modifiers such as `.prevent`, `.stop`, `.outside`, `.window`, `.once` or `.debounce` change how the
+listener is attached.
+
+[Documentation](https://alpinejs.dev/directives/on)"###,
+    "x-text" =>
+r###"sets the text content of the element to the result of an expression
+
+```html
+<span x-text="username"></span>
+```
+
+[Documentation](https://alpinejs.dev/directives/text)"###,
+    "x-html" =>
+r###"sets the inner HTML of the element to the result of an expression
+
+Only use it on trusted content, as it may open up XSS vulnerabilities.
+
+[Documentation](https://alpinejs.dev/directives/html)"###,
+    "x-model" =>
+r###"binds the value of an input to data
+
+```html
+<input type="text" x-model.debounce="search">
+```
+
+[Documentation](https://alpinejs.dev/directives/model)"###,
+    "x-modelable" =>
+r###"exposes a property of a component to be bound with `x-model` from outside it
+
+[Documentation](https://alpinejs.dev/directives/modelable)"###,
+    "x-for" =>
+r###"creates an element for each item of a list, on a `<template>` with a single root element
+
+```html
+<template x-for="post in posts" :key="post.id">
+  <h2 x-text="post.title"></h2>
+</template>
+```
+
+[Documentation](https://alpinejs.dev/directives/for)"###,
+    "x-transition" =>
+r###"transitions the element when it is shown or hidden
+
+```html
+<div x-show="open" x-transition.duration.500ms>...</div>
+```
+
+[Documentation](https://alpinejs.dev/directives/transition)"###,
+    "x-effect" =>
+r###"runs an expression again whenever one of its dependencies changes
+
+[Documentation](https://alpinejs.dev/directives/effect)"###,
+    "x-ignore" =>
+r###"stops Alpine from initializing the element and its children
+
+[Documentation](https://alpinejs.dev/directives/ignore)"###,
+    "x-ref" =>
+r###"names the element so that it can be accessed with `$refs`
+
+```html
+<span x-ref="text">Hello 👋</span>
+<button @click="$refs.text.remove()">Remove Text</button>
+```
+
+[Documentation](https://alpinejs.dev/directives/ref)"###,
+    "x-cloak" =>
+r###"hides the element until Alpine is initialized, along with a `[x-cloak] { display: none }` style
+
+[Documentation](https://alpinejs.dev/directives/cloak)"###,
+    "x-teleport" =>
+r###"moves the contents of a `<template>` to the element matching a selector, such as `body`
+
+[Documentation](https://alpinejs.dev/directives/teleport)"###,
+    "x-if" =>
+r###"adds the element to the page when an expression is true, on a `<template>` with a single root element
+
+```html
+<template x-if="open">
+  <div>Contents...</div>
+</template>
+```
+
+[Documentation](https://alpinejs.dev/directives/if)"###,
+    "x-id" =>
+r###"declares a scope for the ids generated by `$id`
+
+[Documentation](https://alpinejs.dev/directives/id)"###,
+};
+
+/// The modifiers each directive accepts, with a description of each
+pub static MODIFIERS: phf::Map<&'static str, &'static [(&'static str, &'static str)]> = phf_map! {
+    "x-on" => &[
+        ("prevent", "calls `preventDefault()` on the event"),
+        ("stop", "calls `stopPropagation()` on the event"),
+        ("outside", "listens for events outside of the element"),
+        ("window", "listens for the event on the window"),
+        ("document", "listens for the event on the document"),
+        ("once", "only handles the event once"),
+        ("debounce", "waits for the events to stop for a while, `250ms` by default, before handling them"),
+        ("throttle", "handles the event at most once per interval, `250ms` by default"),
+        ("self", "only handles events dispatched on the element itself"),
+        ("camel", "listens for the camel cased version of the event name"),
+        ("dot", "listens for the event name with dashes replaced by dots"),
+        ("passive", "attaches a passive listener"),
+        ("capture", "handles the event in the capture phase"),
+        ("enter", "only handles the Enter key"),
+        ("escape", "only handles the Escape key"),
+        ("space", "only handles the Space key"),
+        ("tab", "only handles the Tab key"),
+        ("shift", "only handles keys pressed with Shift"),
+        ("ctrl", "only handles keys pressed with Control"),
+        ("alt", "only handles keys pressed with Alt"),
+        ("meta", "only handles keys pressed with Meta"),
+    ],
+    "x-model" => &[
+        ("lazy", "updates the data on `change` instead of `input`"),
+        ("number", "stores the value as a number"),
+        ("boolean", "stores the value as a boolean"),
+        ("debounce", "waits for the input to stop for a while, `250ms` by default, before updating"),
+        ("throttle", "updates at most once per interval, `250ms` by default"),
+        ("fill", "initializes the data with the value of the input"),
+    ],
+    "x-transition" => &[
+        ("duration", "sets the duration of the transition, as in `.duration.500ms`"),
+        ("delay", "delays the transition, as in `.delay.50ms`"),
+        ("opacity", "only transitions the opacity"),
+        ("scale", "only transitions the scale, optionally by a percentage, as in `.scale.80`"),
+        ("origin", "sets the origin of the scale transition, as in `.origin.top`"),
+        ("in", "only applies the modifiers that follow when entering"),
+        ("out", "only applies the modifiers that follow when leaving"),
+    ],
+    "x-show" => &[
+        ("important", "hides the element with `display: none !important`"),
+    ],
+};
+
+/// The magic properties available in expressions
+pub static MAGICS: phf::Map<&'static str, &'static str> = phf_map! {
+    "$el" => "the current DOM element",
+    "$refs" => "the elements inside the component named with `x-ref`",
+    "$store" => "the global stores registered with `Alpine.store()`",
+    "$watch" => "watches a property of the component, calling back with its new and old values when it changes",
+    "$dispatch" => "dispatches a browser event from the current element",
+    "$nextTick" => "runs a callback after Alpine has updated the DOM",
+    "$root" => "the root element of the component, the closest one with `x-data`",
+    "$data" => "the data of the component, merged with that of the components around it",
+    "$id" => "generates an id that is unique on the page, scoped by `x-id`",
+    "$event" => "the event being handled, in `x-on` expressions",
+};
+
+/// Returns the directive of an attribute, without its argument and modifiers, along with the
+/// modifiers
+///
+/// `@click.outside` is `x-on` with the `outside` modifier and `:class` is `x-bind`
+pub fn directive(key: &str) -> Option<(&'static str, Vec<&str>)> {
+    let mut parts = key.split('.');
+    let name = parts.next()?;
+    let name = if name.starts_with('@') {
+        "x-on"
+    } else if name.starts_with(':') {
+        "x-bind"
+    } else {
+        name.split_once(':').map_or(name, |(name, _)| name)
+    };
+    let directive = ATTRIBUTES.iter().find(|d| d.eq_ignore_ascii_case(name))?;
+    Some((directive, parts.collect()))
+}
+
+/// Describes the attribute `key` along with its modifiers
+pub fn describe(key: &str) -> Option<String> {
+    let (directive, modifiers) = directive(key)?;
+    let mut doc = String::from(*DESCRIPTIONS.get(directive)?);
+    let known = MODIFIERS.get(directive).copied().unwrap_or_default();
+    for modifier in modifiers {
+        if let Some((name, description)) = known.iter().find(|(name, _)| *name == modifier) {
+            let _ = write!(doc, "\n\n`.{name}`: {description}");
+        }
+    }
+    Some(doc)
+}
+
+/// Completes the attribute `key` being typed in `file`, with directives or, after a dot, the modifiers
+/// of its directive
+pub fn completions(file: &FileData, key: &str) -> Vec<CompletionItem> {
+    let range = file.range_of(key);
+    if let Some((prefix, _)) = key.rsplit_once('.') {
+        let Some((directive, _)) = directive(key) else {
+            return Vec::new();
+        };
+        return MODIFIERS
+            .get(directive)
+            .copied()
+            .unwrap_or_default()
+            .iter()
+            .map(|(name, description)| CompletionItem {
+                detail: Some((*description).to_string()),
+                ..completion(
+                    &format!("{prefix}.{name}"),
+                    CompletionItemKind::PROPERTY,
+                    range,
+                )
+            })
+            .collect();
+    }
+    ATTRIBUTES
+        .iter()
+        .filter(|name| name.starts_with(key))
+        .map(|name| CompletionItem {
+            documentation: DESCRIPTIONS.get(*name).map(|doc| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: (*doc).to_string(),
+                })
+            }),
+            ..completion(name, CompletionItemKind::FIELD, range)
+        })
+        .collect()
+}
+
+/// Returns the bounds of the magic property, starting with `$`, around `cursor` in `value`
+fn magic_at(value: &str, cursor: usize) -> Option<(usize, usize)> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = value[..cursor]
+        .rfind(|c: char| !is_ident(c))
+        .filter(|&i| value[i..].starts_with('$'))?;
+    let end = value[cursor..]
+        .find(|c: char| !is_ident(c))
+        .map_or(value.len(), |i| cursor + i);
+    Some((start, end))
+}
+
+/// Completes the magic property under `cursor` in the value of a directive
+pub fn complete_magics(file: &FileData, value: &str, cursor: usize) -> Option<Vec<CompletionItem>> {
+    let (start, end) = magic_at(value, cursor)?;
+    let range = file.range_of(&value[start..end]);
+    Some(
+        MAGICS
+            .entries()
+            .map(|(name, description)| CompletionItem {
+                detail: Some((*description).to_string()),
+                ..completion(name, CompletionItemKind::VARIABLE, range)
+            })
+            .collect(),
+    )
+}
+
+/// Describes the magic property under `cursor` in the value of a directive, returning it and its
+/// description
+pub fn magic(value: &str, cursor: usize) -> Option<(&str, &'static str)> {
+    let (start, end) = magic_at(value, cursor)?;
+    let name = &value[start..end];
+    Some((name, MAGICS.get(name)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_directives_and_modifiers() {
+        assert_eq!(
+            directive("@click.outside.prevent"),
+            Some(("x-on", vec!["outside", "prevent"]))
+        );
+        assert_eq!(directive(":class"), Some(("x-bind", vec![])));
+        assert_eq!(directive("x-on:keyup.enter"), Some(("x-on", vec!["enter"])));
+        assert_eq!(directive("X-Text"), Some(("x-text", vec![])));
+        assert_eq!(directive("x-nope"), None);
+        assert_eq!(directive("hx-get"), None);
+    }
+
+    #[test]
+    fn describes_known_modifiers() {
+        let doc = describe("@click.outside.nope").unwrap();
+        assert!(doc.contains("`.outside`"));
+        assert!(!doc.contains("`.nope`"));
+    }
+
+    #[test]
+    fn finds_the_magic_under_the_cursor() {
+        let value = "$refs.input.focus(); open = !$root";
+        assert_eq!(magic(value, 3), Some(("$refs", MAGICS["$refs"])));
+        assert_eq!(
+            magic(value, value.len()).map(|(name, _)| name),
+            Some("$root")
+        );
+        assert_eq!(magic(value, 8), None);
+        assert_eq!(magic_at("x = $", 5), Some((4, 5)));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// User settings, sent as `initializationOptions` or through `workspace/didChangeConfiguration`
+///
+/// ```json
+/// {
+///     "fragments": {
+///         "/contacts": "rows.html",
+///         "/contacts/archive": "archive_ui.html"
+///     },
+///     "jsValues": "error",
+///     "libraries": ["alpine", "datastar"]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    /// Maps routes to the name of the template they render as a response
+    ///
+    /// Route parameters may be written as `<id>`, `{id}` or `:id`. Template names are resolved the same
+    /// way as `{% include %}`s
+    pub fragments: HashMap<String, String>,
+    /// How to treat `hx-vals` and `hx-headers` that evaluate JavaScript, which is a way to inject code
+    /// when attribute values are built from user input
+    pub js_values: JsPolicy,
+    /// The libraries used alongside htmx in the workspace, whose attributes are completed, described and
+    /// checked
+    pub libraries: Vec<Library>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fragments: HashMap::new(),
+            js_values: JsPolicy::default(),
+            libraries: vec![Library::Alpine, Library::Hyperscript],
+        }
+    }
+}
+
+/// What to do about attribute values that evaluate JavaScript, with a `js:` or `javascript:` prefix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JsPolicy {
+    Allow,
+    #[default]
+    Warn,
+    Error,
+}
+
+/// A library used alongside htmx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Library {
+    Alpine,
+    Hyperscript,
+    Datastar,
+}
+
+impl Config {
+    /// Returns whether `library` is used in the workspace
+    pub fn uses(&self, library: Library) -> bool {
+        self.libraries.contains(&library)
+    }
+
+    /// Reads the config from client settings, which may be nested under an `htmx` key
+    ///
+    /// Returns `None` when the settings hold no htmx config at all, e.g. when a client sends `null` or
+    /// only the settings of other servers, so that the current config is kept
+    pub fn from_settings(settings: serde_json::Value) -> Result<Option<Self>, serde_json::Error> {
+        match settings {
+            serde_json::Value::Object(mut settings) if settings.contains_key("htmx") => {
+                serde_json::from_value(settings.remove("htmx").unwrap_or_default()).map(Some)
+            }
+            serde_json::Value::Object(settings)
+                if ["fragments", "jsValues", "libraries"]
+                    .iter()
+                    .any(|key| settings.contains_key(*key)) =>
+            {
+                serde_json::from_value(settings.into()).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_settings_nested_or_not() {
+        let config =
+            Config::from_settings(json!({ "htmx": { "fragments": { "/contacts": "rows.html" } } }))
+                .unwrap()
+                .unwrap();
+        assert_eq!(config.fragments["/contacts"], "rows.html");
+        let config = Config::from_settings(json!({ "fragments": { "/a": "a.html" } }))
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.fragments["/a"], "a.html");
+        assert!(Config::from_settings(json!({ "fragments": [] })).is_err());
+    }
+
+    #[test]
+    fn settings_without_htmx_config_are_ignored() {
+        assert!(Config::from_settings(serde_json::Value::Null)
+            .unwrap()
+            .is_none());
+        assert!(
+            Config::from_settings(json!({ "python": { "analysis": {} } }))
+                .unwrap()
+                .is_none()
+        );
+        assert!(Config::from_settings(json!({ "htmx": {} }))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn libraries_default_to_alpine_and_hyperscript() {
+        let config = Config::default();
+        assert!(config.uses(Library::Alpine) && config.uses(Library::Hyperscript));
+        assert!(!config.uses(Library::Datastar));
+        let config = Config::from_settings(json!({ "libraries": ["datastar"] }))
+            .unwrap()
+            .unwrap();
+        assert!(config.uses(Library::Datastar));
+        assert!(!config.uses(Library::Alpine));
+    }
+}
@@ -0,0 +1,500 @@
+//! Datastar, which binds reactive signals to elements with `data-*` attributes
+//!
+//! Attributes are written as `data-on-click__debounce.500ms` or `data-on:click__debounce.500ms`: the
+//! name of the attribute, an optional key such as an event or signal name, then modifiers prefixed by
+//! `__` with arguments separated by dots
+
+use std::sync::LazyLock;
+
+use itertools::Itertools;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, Documentation, MarkupContent, MarkupKind,
+};
+use regex::Regex;
+
+use crate::{diagnostics::diagnostic, textstore::FileData, values::completion};
+
+/// Whether an attribute takes a key after its name
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    None,
+    Optional,
+    Required,
+}
+
+/// What an attribute expects as its value
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    /// An expression, which may reference signals as `$name`
+    Expression,
+    /// An object of names to expressions, or an expression when the attribute has a key
+    ObjectUnlessKeyed,
+    /// The name of a signal, or nothing when the attribute has a key naming the signal
+    SignalUnlessKeyed,
+    /// Anything, including nothing
+    Any,
+}
+
+pub struct Attribute {
+    pub name: &'static str,
+    pub key: Key,
+    pub value: Value,
+    pub description: &'static str,
+}
+
+pub static ATTRIBUTES: &[Attribute] = &[
+    Attribute {
+        name: "data-attr",
+        key: Key::Optional,
+        value: Value::ObjectUnlessKeyed,
+        description: "sets an attribute to the result of an expression, as in `data-attr-title=\"$title\"`, \
+            or several with an object",
+    },
+    Attribute {
+        name: "data-bind",
+        key: Key::Optional,
+        value: Value::SignalUnlessKeyed,
+        description: "binds the value of an input to a signal, named by the key as in `data-bind-query` or \
+            by the value as in `data-bind=\"query\"`",
+    },
+    Attribute {
+        name: "data-class",
+        key: Key::Optional,
+        value: Value::ObjectUnlessKeyed,
+        description: "adds a class when an expression is true, as in `data-class-hidden=\"$done\"`, or \
+            several classes with an object",
+    },
+    Attribute {
+        name: "data-computed",
+        key: Key::Required,
+        value: Value::Expression,
+        description: "creates a read only signal computed from an expression, as in \
+            `data-computed-total=\"$price * $count\"`",
+    },
+    Attribute {
+        name: "data-effect",
+        key: Key::None,
+        value: Value::Expression,
+        description: "runs an expression whenever the signals it references change",
+    },
+    Attribute {
+        name: "data-ignore",
+        key: Key::None,
+        value: Value::Any,
+        description: "stops Datastar from processing the element and its children",
+    },
+    Attribute {
+        name: "data-ignore-morph",
+        key: Key::None,
+        value: Value::Any,
+        description: "keeps the element and its children as they are when morphing",
+    },
+    Attribute {
+        name: "data-indicator",
+        key: Key::Optional,
+        value: Value::SignalUnlessKeyed,
+        description: "sets a signal to true while a request from the element is in flight",
+    },
+    Attribute {
+        name: "data-json-signals",
+        key: Key::None,
+        value: Value::Any,
+        description: "shows the signals as JSON in the element, for debugging",
+    },
+    Attribute {
+        name: "data-on",
+        key: Key::Required,
+        value: Value::Expression,
+        description: "runs an expression when an event is dispatched on the element, as in \
+            `data-on-click=\"@get('/endpoint')\"`",
+    },
+    Attribute {
+        name: "data-on-intersect",
+        key: Key::None,
+        value: Value::Expression,
+        description: "runs an expression when the element enters the viewport",
+    },
+    Attribute {
+        name: "data-on-interval",
+        key: Key::None,
+        value: Value::Expression,
+        description: "runs an expression at an interval, every second by default",
+    },
+    Attribute {
+        name: "data-on-signal-patch",
+        key: Key::None,
+        value: Value::Expression,
+        description: "runs an expression whenever signals are changed",
+    },
+    Attribute {
+        name: "data-preserve-attr",
+        key: Key::None,
+        value: Value::Any,
+        description: "keeps the listed attributes as they are when morphing",
+    },
+    Attribute {
+        name: "data-ref",
+        key: Key::Optional,
+        value: Value::SignalUnlessKeyed,
+        description: "creates a signal referencing the element",
+    },
+    Attribute {
+        name: "data-show",
+        key: Key::None,
+        value: Value::Expression,
+        description: "shows or hides the element depending on an expression",
+    },
+    Attribute {
+        name: "data-signals",
+        key: Key::Optional,
+        value: Value::ObjectUnlessKeyed,
+        description: "creates signals, one named by the key as in `data-signals-count=\"0\"`, or several with \
+            an object as in `data-signals=\"{count: 0}\"`",
+    },
+    Attribute {
+        name: "data-style",
+        key: Key::Optional,
+        value: Value::ObjectUnlessKeyed,
+        description: "sets a style property to the result of an expression, or several with an object",
+    },
+    Attribute {
+        name: "data-text",
+        key: Key::None,
+        value: Value::Expression,
+        description: "sets the text content of the element to the result of an expression",
+    },
+];
+
+/// The modifiers of `data-on` and the attributes handling events, with a description of each
+pub static EVENT_MODIFIERS: &[(&str, &str)] = &[
+    ("once", "only handles the event once"),
+    ("passive", "attaches a passive listener"),
+    ("capture", "handles the event in the capture phase"),
+    ("delay", "delays handling the event, as in `__delay.500ms`"),
+    (
+        "debounce",
+        "waits for the events to stop for a while, as in `__debounce.500ms`",
+    ),
+    (
+        "throttle",
+        "handles the event at most once per interval, as in `__throttle.500ms`",
+    ),
+    (
+        "viewtransition",
+        "wraps the expression in a view transition",
+    ),
+    ("window", "listens for the event on the window"),
+    ("outside", "listens for events outside of the element"),
+    ("prevent", "calls `preventDefault()` on the event"),
+    ("stop", "calls `stopPropagation()` on the event"),
+    (
+        "case",
+        "converts the case of the event name, as in `__case.kebab`",
+    ),
+];
+
+/// A Datastar attribute as written on an element
+pub struct Parsed<'a> {
+    pub attribute: &'static Attribute,
+    /// The key after the name, as in `click` in `data-on-click`
+    pub key: Option<&'a str>,
+    pub modifiers: Vec<&'a str>,
+}
+
+/// Parses the name of a Datastar attribute
+pub fn parse(name: &str) -> Option<Parsed<'_>> {
+    let mut parts = name.split("__");
+    let head = parts.next()?;
+    let attribute = ATTRIBUTES
+        .iter()
+        .filter(|attribute| {
+            head.strip_prefix(attribute.name)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', ':']))
+        })
+        .max_by_key(|attribute| attribute.name.len())?;
+    let key = Some(&head[attribute.name.len()..])
+        .filter(|key| !key.is_empty())
+        .map(|key| &key[1..]);
+    Some(Parsed {
+        attribute,
+        key,
+        modifiers: parts.collect(),
+    })
+}
+
+/// Describes the attribute `name` along with its modifiers
+pub fn describe(name: &str) -> Option<String> {
+    let parsed = parse(name)?;
+    let mut doc = format!(
+        "{}\n\n[Documentation](https://data-star.dev/reference/attributes#{})",
+        parsed.attribute.description, parsed.attribute.name
+    );
+    for modifier in parsed.modifiers {
+        let modifier = modifier.split('.').next().unwrap_or(modifier);
+        if let Some((name, description)) = EVENT_MODIFIERS.iter().find(|(m, _)| *m == modifier) {
+            doc = format!("{doc}\n\n`__{name}`: {description}");
+        }
+    }
+    Some(doc)
+}
+
+/// Completes the attribute `name` being typed in `file` or, after `__`, the modifiers of an attribute
+/// handling events
+pub fn completions(file: &FileData, name: &str) -> Vec<CompletionItem> {
+    let range = file.range_of(name);
+    if let Some((prefix, _)) = name.rsplit_once("__") {
+        let handles_events =
+            parse(name).is_some_and(|parsed| parsed.attribute.name.starts_with("data-on"));
+        return EVENT_MODIFIERS
+            .iter()
+            .filter(|_| handles_events)
+            .map(|(modifier, description)| CompletionItem {
+                detail: Some((*description).to_string()),
+                ..completion(
+                    &format!("{prefix}__{modifier}"),
+                    CompletionItemKind::PROPERTY,
+                    range,
+                )
+            })
+            .collect();
+    }
+    ATTRIBUTES
+        .iter()
+        .filter(|attribute| attribute.name.starts_with(name))
+        .map(|attribute| CompletionItem {
+            detail: Some("Datastar".into()),
+            documentation: describe(attribute.name).map(|doc| {
+                Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: doc,
+                })
+            }),
+            ..completion(attribute.name, CompletionItemKind::FIELD, range)
+        })
+        .collect()
+}
+
+static SIGNAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][\w-]*(\.[A-Za-z_][\w-]*)*$").unwrap());
+
+/// The names of the signals declared with an object in `data-signals`
+static OBJECT_KEYS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[{,]\s*["']?([A-Za-z_][\w-]*)["']?\s*:"#).unwrap());
+
+/// Checks the keys and values of Datastar attributes
+pub fn check(file: &FileData) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (_, name, value) in file.attributes() {
+        let Some(Parsed { attribute, key, .. }) = parse(name) else {
+            continue;
+        };
+        let attribute_name = attribute.name;
+        match (attribute.key, key) {
+            (Key::Required, None) => diagnostics.push(diagnostic(
+                file,
+                name,
+                format!(
+                    "{attribute_name}: expected a key after the name, as in `{attribute_name}-<key>`"
+                ),
+            )),
+            (Key::None, Some(key)) => diagnostics.push(diagnostic(
+                file,
+                name,
+                format!("{attribute_name}: doesn't take a key, found `{key}`"),
+            )),
+            _ => {}
+        }
+        if file.is_templated(value) {
+            continue;
+        }
+        let trimmed = value.trim();
+        let message = match (attribute.value, key) {
+            (Value::Expression, _) | (Value::ObjectUnlessKeyed, Some(_)) if trimmed.is_empty() => {
+                "expected an expression"
+            }
+            (Value::ObjectUnlessKeyed, None)
+                if !(trimmed.starts_with('{') && trimmed.ends_with('}')) =>
+            {
+                "expected an object, as in `{name: value}`"
+            }
+            (Value::SignalUnlessKeyed, None) if !SIGNAL.is_match(trimmed) => {
+                "expected the name of a signal"
+            }
+            _ => continue,
+        };
+        diagnostics.push(diagnostic(
+            file,
+            value,
+            format!("{attribute_name}: {message}"),
+        ));
+    }
+    diagnostics
+}
+
+/// The attributes that declare signals, named by their key or value
+const DECLARING: &[&str] = &[
+    "data-signals",
+    "data-computed",
+    "data-bind",
+    "data-ref",
+    "data-indicator",
+];
+
+/// Converts the words of `key`, separated by dashes in attribute names, to `case` (`camel`, `kebab`,
+/// `snake` or `pascal`) like Datastar does for the segments of signal names
+fn convert_case(key: &str, case: &str) -> String {
+    key.split('.')
+        .map(|segment| {
+            let mut words = segment.split('-').filter(|word| !word.is_empty());
+            let capitalize = |word: &str| {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_ascii_uppercase().to_string() + chars.as_str()
+                })
+            };
+            match case {
+                "kebab" => words.join("-"),
+                "snake" => words.join("_"),
+                "pascal" => words.map(capitalize).join(""),
+                _ => words
+                    .next()
+                    .into_iter()
+                    .map(str::to_string)
+                    .chain(words.map(capitalize))
+                    .join(""),
+            }
+        })
+        .join(".")
+}
+
+/// Returns the name of the signal declared by the key of an attribute, camel cased unless a `__case`
+/// modifier says otherwise
+fn key_signal(parsed: &Parsed) -> Option<String> {
+    let case = parsed
+        .modifiers
+        .iter()
+        .find_map(|modifier| modifier.strip_prefix("case."))
+        .unwrap_or("camel");
+    Some(convert_case(parsed.key?, case))
+}
+
+/// Returns the names of the signals declared in `file`
+fn signals(file: &FileData) -> Vec<String> {
+    file.attributes()
+        .filter_map(|(_, name, value)| Some((parse(name)?, value)))
+        .filter(|(parsed, _)| DECLARING.contains(&parsed.attribute.name))
+        .flat_map(|(parsed, value)| {
+            let from_value: Vec<&str> = match (parsed.attribute.value, parsed.key) {
+                (Value::ObjectUnlessKeyed, None) => OBJECT_KEYS
+                    .captures_iter(value)
+                    .filter_map(|c| Some(c.get(1)?.as_str()))
+                    .collect(),
+                (Value::SignalUnlessKeyed, None) => vec![value.trim()],
+                _ => Vec::new(),
+            };
+            // Keys are written in attribute names, which are case insensitive, values are used as is
+            let from_key = parsed
+                .key
+                .filter(|key| SIGNAL.is_match(key) && !file.is_templated(key))
+                .and_then(|_| key_signal(&parsed));
+            from_key
+                .into_iter()
+                .chain(
+                    from_value
+                        .into_iter()
+                        .filter(|signal| SIGNAL.is_match(signal) && !file.is_templated(signal))
+                        .map(str::to_string),
+                )
+                .collect::<Vec<_>>()
+        })
+        .unique()
+        .collect()
+}
+
+/// Completes the signal name under `cursor` in the value of the attribute `name`, after a `$` in
+/// expressions, or anywhere in the values naming a signal
+pub fn complete_signals(
+    file: &FileData,
+    name: &str,
+    value: &str,
+    cursor: usize,
+) -> Option<Vec<CompletionItem>> {
+    let parsed = parse(name)?;
+    let is_name = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.');
+    let start = value[..cursor]
+        .rfind(|c: char| !is_name(c))
+        .map_or(0, |i| i + 1);
+    let end = value[cursor..]
+        .find(|c: char| !is_name(c))
+        .map_or(value.len(), |i| cursor + i);
+    let prefix = match (parsed.attribute.value, parsed.key) {
+        (Value::SignalUnlessKeyed, None) => "",
+        (Value::Any, _) => return None,
+        _ if value[..start].ends_with('$') => "$",
+        _ => return Some(Vec::new()),
+    };
+    let start = start - prefix.len();
+    let range = file.range_of(&value[start..end]);
+    Some(
+        signals(file)
+            .into_iter()
+            .map(|signal| {
+                completion(
+                    &format!("{prefix}{signal}"),
+                    CompletionItemKind::VARIABLE,
+                    range,
+                )
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textstore::TextStore;
+
+    #[test]
+    fn parses_keys_and_modifiers() {
+        let parsed = parse("data-on-click__debounce.500ms__window").unwrap();
+        assert_eq!(parsed.attribute.name, "data-on");
+        assert_eq!(parsed.key, Some("click"));
+        assert_eq!(parsed.modifiers, ["debounce.500ms", "window"]);
+
+        let parsed = parse("data-on:keydown").unwrap();
+        assert_eq!(parsed.key, Some("keydown"));
+        assert!(parse("data-show").unwrap().key.is_none());
+        assert!(parse("data-showing").is_none());
+        assert!(parse("hx-get").is_none());
+    }
+
+    #[test]
+    fn the_longest_attribute_name_wins() {
+        let parsed = parse("data-on-intersect").unwrap();
+        assert_eq!(parsed.attribute.name, "data-on-intersect");
+        assert!(parsed.key.is_none());
+    }
+
+    #[test]
+    fn converts_keys_to_each_case() {
+        assert_eq!(convert_case("user-name", "camel"), "userName");
+        assert_eq!(convert_case("user-name", "kebab"), "user-name");
+        assert_eq!(convert_case("user-name", "snake"), "user_name");
+        assert_eq!(convert_case("user-name", "pascal"), "UserName");
+        assert_eq!(convert_case("form.first-name", "camel"), "form.firstName");
+        assert_eq!(convert_case("count", "camel"), "count");
+    }
+
+    #[test]
+    fn signals_from_keys_are_camel_cased_unless_told_otherwise() {
+        let html = r#"<div data-signals="{ count: 0, 'first_name': '' }">
+            <input data-bind-user-name=""><input data-bind-last-name__case.kebab=""><input data-bind="email">
+        </div>"#;
+        let store = TextStore::with_page(html);
+        let file = store.files.values().next().unwrap();
+        assert_eq!(
+            signals(file),
+            ["count", "first_name", "userName", "last-name", "email"]
+        );
+    }
+}
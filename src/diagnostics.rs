@@ -0,0 +1,659 @@
+use itertools::Itertools;
+use lsp_types::{Diagnostic, DiagnosticSeverity, Uri};
+
+use crate::{
+    config::{Config, JsPolicy, Library},
+    datastar, extensions,
+    fragments::{self, RequestGraph},
+    library::{Grammar, LibraryIndex},
+    routes::{self, RouteIndex},
+    selector::{self, ExtendedSelector},
+    sse,
+    textstore::{attribute, FileData, TextStore},
+    values::{self, JsonError},
+    workspace::TemplateGraph,
+};
+
+/// Attributes whose values are extended selectors resolved against the current page
+const SELECTOR_ATTRIBUTES: &[&str] = &[
+    "hx-target",
+    "hx-indicator",
+    "hx-include",
+    "hx-disabled-elt",
+    "hx-sync",
+];
+
+pub fn diagnostic(file: &FileData, value: &str, message: String) -> Diagnostic {
+    Diagnostic {
+        range: file.range_of(value),
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some("htmx-lsp".into()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Returns whether `selector` matches an element in any of `files`
+///
+/// Selectors that `tl` can't parse, such as ones with pseudo classes, are assumed to match
+fn resolves(selector: &str, files: &[&FileData]) -> bool {
+    if selector.contains(':') {
+        return true;
+    }
+    files.iter().any(|file| {
+        file.dom
+            .query_selector(selector)
+            .is_none_or(|mut matches| matches.next().is_some())
+    })
+}
+
+/// Checks that selectors in `file` point at elements that exist in the page it ends up in
+///
+/// Attributes taking a list of selectors have each of them checked, since a CSS selector list matches as
+/// soon as any of its selectors does
+fn check_selectors(file: &FileData, composed: &[&FileData]) -> Vec<Diagnostic> {
+    file.attributes()
+        .filter(|(_, key, _)| SELECTOR_ATTRIBUTES.contains(key))
+        .filter(|(_, _, value)| !file.is_templated(value))
+        .flat_map(|(_, key, value)| {
+            let selectors: Vec<&str> = match key {
+                "hx-sync" => vec![values::split_sync(value).0],
+                "hx-target" => vec![value],
+                _ => selector::split_list(value).collect(),
+            };
+            selectors.into_iter().filter_map(move |value| {
+                let selector = ExtendedSelector::parse(value).absolute()?;
+                (!resolves(selector, composed)).then(|| {
+                    diagnostic(
+                        file,
+                        value,
+                        format!("{key}: no element matches `{selector}`"),
+                    )
+                })
+            })
+        })
+        .collect()
+}
+
+/// Checks that `hx-select` and `hx-select-oob` pick elements that exist in the fragments rendered in
+/// response to requests from `file`
+fn check_responses(
+    file: &FileData,
+    composed: &[&FileData],
+    textstore: &TextStore,
+    graph: &TemplateGraph,
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut push = |diagnostic| {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    };
+    for request in routes::requests(file) {
+        let Some(name) = fragments::fragment_for(config, request.url) else {
+            continue;
+        };
+        let Some(fragment) = graph.resolve(name, None) else {
+            continue;
+        };
+        let response: Vec<&FileData> = graph
+            .rendered(fragment)
+            .into_iter()
+            .filter_map(|uri| textstore.files.get(uri))
+            .collect();
+        let url = request.url.trim();
+        let method = request.method();
+        if let Some(value) = file.inherited(request.node, "hx-select") {
+            let selector = ExtendedSelector::parse(value).absolute();
+            if let Some(selector) = selector.filter(|_| !file.is_templated(value)) {
+                if !resolves(selector, &response) {
+                    push(diagnostic(
+                        file,
+                        value,
+                        format!("hx-select: no element matches `{selector}` in the response to {method} {url} ({name})"),
+                    ));
+                }
+            }
+        }
+        if let Some(value) = file.inherited(request.node, "hx-select-oob") {
+            // Each entry is an id, optionally followed by the swap style, e.g. `#alert:afterbegin`
+            for entry in value.split(',').filter(|e| !file.is_templated(e)) {
+                let id = entry.split(':').next().unwrap_or(entry).trim();
+                if id.is_empty() {
+                    continue;
+                }
+                let selector = format!("#{}", id.trim_start_matches('#'));
+                if !resolves(&selector, &response) {
+                    push(diagnostic(
+                        file,
+                        id,
+                        format!("hx-select-oob: no element matches `{selector}` in the response to {method} {url} ({name})"),
+                    ));
+                } else if !resolves(&selector, composed) {
+                    push(diagnostic(
+                        file,
+                        id,
+                        format!("hx-select-oob: no element matches `{selector}` in the page to swap it into"),
+                    ));
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Checks that out of band swaps in `file` have a target in the pages the response is swapped into
+///
+/// An `hx-swap-oob` of `true` or of just a swap style targets the element with the same id, while
+/// `<style>:<selector>` targets the selector
+fn check_swap_oob(file: &FileData, receivers: &[&FileData]) -> Vec<Diagnostic> {
+    if receivers.is_empty() {
+        return Vec::new();
+    }
+    file.tags()
+        .filter_map(|(_, tag)| {
+            let value = attribute(tag, "hx-swap-oob")?;
+            if file.is_templated(value) {
+                return None;
+            }
+            let selector = if let Some((_, selector)) = value.split_once(':') {
+                selector.trim().to_string()
+            } else {
+                let id = attribute(tag, "id");
+                if id.is_some_and(|id| file.is_templated(id)) {
+                    return None;
+                }
+                let Some(id) = id.filter(|id| !id.trim().is_empty()) else {
+                    return Some(diagnostic(
+                        file,
+                        value,
+                        "hx-swap-oob: element has no id to swap it by".into(),
+                    ));
+                };
+                format!("#{}", id.trim())
+            };
+            (!resolves(&selector, receivers)).then(|| {
+                diagnostic(
+                    file,
+                    value,
+                    format!("hx-swap-oob: no element matches `{selector}` in the pages requesting this fragment"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Checks that requests from `file` go to a route registered by the backend, with a method it accepts
+///
+/// Only urls that are absolute paths are checked, since relative ones depend on the url of the page
+fn check_routes(file: &FileData, routes: &RouteIndex) -> Vec<Diagnostic> {
+    if routes.is_empty() {
+        return Vec::new();
+    }
+    routes::requests(file)
+        .filter(|request| {
+            let url = request.url.trim();
+            url.starts_with('/') && !url.starts_with("//")
+        })
+        .filter_map(|request| {
+            let path = file.source_of(routes::url_path(request.url)).trim();
+            let matching: Vec<_> = routes.matching(request.url).collect();
+            if matching.is_empty() && routes.has_unknown_prefixes() {
+                return None;
+            }
+            if matching.is_empty() {
+                return Some(diagnostic(
+                    file,
+                    request.url,
+                    format!("{}: no route matches `{path}`", request.verb),
+                ));
+            }
+            // Parameters can make several routes match, any of which may be the one handling the request
+            if matching.iter().any(|(_, route)| route.methods.is_empty()) {
+                return None;
+            }
+            let method = request.method();
+            let mut methods: Vec<&str> = matching
+                .iter()
+                .flat_map(|(_, route)| route.methods.iter().map(String::as_str))
+                .collect();
+            if methods.contains(&method.as_str()) {
+                return None;
+            }
+            methods.sort_unstable();
+            methods.dedup();
+            Some(diagnostic(
+                file,
+                request.url,
+                format!(
+                    "{}: `{path}` does not accept {method}, only {}",
+                    request.verb,
+                    methods.join(", ")
+                ),
+            ))
+        })
+        .collect()
+}
+
+/// Checks that the elements `hx-include` picks submit values, as forms, named fields or elements
+/// containing named fields do
+///
+/// Plain CSS selectors are resolved against the whole page, while relative ones are resolved in `file`.
+/// Selectors that don't pick anything are either reported by [`check_selectors`] or may pick elements
+/// coming from other templates, so they aren't reported here
+fn check_include(file: &FileData, composed: &[&FileData]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, tag) in file.tags() {
+        let Some(value) = attribute(tag, "hx-include").filter(|v| !file.is_templated(v)) else {
+            continue;
+        };
+        for part in selector::split_list(value) {
+            let selector = ExtendedSelector::parse(part);
+            let matched: Vec<(&FileData, usize)> = match selector.absolute() {
+                Some(css) => composed
+                    .iter()
+                    .filter_map(|other| Some((*other, other.select(css)?)))
+                    .flat_map(|(other, nodes)| nodes.into_iter().map(move |n| (other, n)))
+                    .collect(),
+                None => selector
+                    .resolve(file, i)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|n| (file, n))
+                    .collect(),
+            };
+            if !matched.is_empty() && !matched.iter().any(|(f, n)| f.submits_values(*n)) {
+                diagnostics.push(diagnostic(
+                    file,
+                    part,
+                    format!(
+                        "hx-include: `{part}` picks no form, named field or element containing one"
+                    ),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Checks that attributes added by extensions are only used where their extension is enabled
+fn check_extensions(file: &FileData, composed: &[&FileData]) -> Vec<Diagnostic> {
+    let layouts: Vec<&FileData> = composed
+        .iter()
+        .copied()
+        .filter(|other| !std::ptr::eq(*other, file))
+        .collect();
+    let declared = extensions::declared(&layouts);
+    let mut diagnostics = Vec::new();
+    for (i, tag) in file.tags() {
+        let keys = tag
+            .attributes()
+            .unstable_raw()
+            .iter()
+            .filter_map(|(key, _)| key.try_as_utf8_str());
+        let mut enabled = None;
+        for key in keys {
+            let Some(attribute) = extensions::lookup(key)
+                .filter(|_| !extensions::SHARED_NAMES.contains(&key.to_ascii_lowercase().as_str()))
+            else {
+                continue;
+            };
+            let enabled = enabled.get_or_insert_with(|| extensions::inherited(file, i));
+            if !enabled.contains(attribute.extension) && !declared.contains(attribute.extension) {
+                diagnostics.push(diagnostic(
+                    file,
+                    key,
+                    format!(
+                        "{key} does nothing without the `{0}` extension, enable it with `hx-ext=\"{0}\"` on this element or an ancestor",
+                        attribute.extension
+                    ),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Checks the values of the attributes described by definition files against their grammars
+fn check_library(
+    file: &FileData,
+    composed: &[&FileData],
+    library: &LibraryIndex,
+) -> Vec<Diagnostic> {
+    file.attributes()
+        .filter(|(_, _, value)| !value.trim().is_empty() && !file.is_templated(value))
+        .filter_map(|(_, key, value)| {
+            let expected = match &library.get(key)?.value {
+                Grammar::Any => return None,
+                Grammar::Boolean => {
+                    (!matches!(value, "true" | "false")).then(|| "`true` or `false`".to_string())
+                }
+                Grammar::Enum { values } => (!values.iter().any(|v| v == value))
+                    .then(|| values.iter().map(|v| format!("`{v}`")).join(" or ")),
+                Grammar::Pattern { pattern } => (!pattern.is_match(value))
+                    .then(|| format!("a value matching `{}`", pattern.as_str())),
+                Grammar::Selector => {
+                    let selector = ExtendedSelector::parse(value).absolute()?;
+                    return (!resolves(selector, composed)).then(|| {
+                        diagnostic(
+                            file,
+                            value,
+                            format!("{key}: no element matches `{selector}`"),
+                        )
+                    });
+                }
+                Grammar::Json => {
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(value)
+                        .is_err()
+                        .then(|| "a JSON object".to_string())
+                }
+            }?;
+            Some(diagnostic(
+                file,
+                value,
+                format!("{key}: expected {expected}, found `{value}`"),
+            ))
+        })
+        .collect()
+}
+
+/// Attributes whose values are JSON objects, or JavaScript expressions evaluating to one
+const JSON_ATTRIBUTES: &[&str] = &["hx-vals", "hx-headers"];
+
+/// Checks that `hx-vals` and `hx-headers` hold valid JSON objects, and flags the ones evaluating
+/// JavaScript according to `policy`
+fn check_json(file: &FileData, policy: JsPolicy) -> Vec<Diagnostic> {
+    file.attributes()
+        .filter(|(_, key, _)| JSON_ATTRIBUTES.contains(key))
+        .filter(|(_, _, value)| !file.is_templated(value))
+        .filter_map(|(_, key, value)| {
+            let json = value.trim_start();
+            if let Some(expr) = ["js:", "javascript:"]
+                .iter()
+                .find_map(|prefix| json.strip_prefix(prefix))
+            {
+                let severity = match policy {
+                    JsPolicy::Allow => return None,
+                    JsPolicy::Warn => DiagnosticSeverity::WARNING,
+                    JsPolicy::Error => DiagnosticSeverity::ERROR,
+                };
+                let prefix = &json[..json.len() - expr.len()];
+                return Some(Diagnostic {
+                    severity: Some(severity),
+                    ..diagnostic(
+                        file,
+                        prefix,
+                        format!(
+                            "{key}: evaluates JavaScript, which is unsafe with untrusted input"
+                        ),
+                    )
+                });
+            }
+            match values::parse_json_object(json) {
+                Ok(_) => None,
+                Err(JsonError::NotAnObject) => Some(diagnostic(
+                    file,
+                    value,
+                    format!("{key}: expected a JSON object"),
+                )),
+                Err(JsonError::Invalid { span, message }) => Some(Diagnostic {
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    ..diagnostic(file, &json[span], format!("{key}: invalid JSON: {message}"))
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Computes the diagnostics for the file at `uri`
+///
+/// Fragments that are rendered in response to a request are checked against the pages making that
+/// request as well as the templates they are part of
+pub fn diagnose(
+    uri: &Uri,
+    textstore: &TextStore,
+    config: &Config,
+    routes: &RouteIndex,
+    library: &LibraryIndex,
+) -> Option<Vec<Diagnostic>> {
+    let file = textstore.files.get(uri)?;
+    let graph = TemplateGraph::new(textstore);
+    let requests = RequestGraph::new(textstore, &graph, config);
+    let receivers = requests.receivers(uri, &graph);
+    let mut uris = graph.composed(uri);
+    uris.extend(&receivers);
+    let composed: Vec<&FileData> = uris
+        .into_iter()
+        .filter_map(|uri| textstore.files.get(uri))
+        .collect();
+    let receivers: Vec<&FileData> = receivers
+        .into_iter()
+        .filter_map(|uri| textstore.files.get(uri))
+        .collect();
+    let mut diagnostics = check_selectors(file, &composed);
+    diagnostics.extend(check_responses(file, &composed, textstore, &graph, config));
+    diagnostics.extend(check_swap_oob(file, &receivers));
+    diagnostics.extend(check_routes(file, routes));
+    diagnostics.extend(check_json(file, config.js_values));
+    diagnostics.extend(check_include(file, &composed));
+    diagnostics.extend(check_extensions(file, &composed));
+    diagnostics.extend(sse::check(file, &composed));
+    diagnostics.extend(values::check(file));
+    diagnostics.extend(check_library(file, &composed, library));
+    if config.uses(Library::Datastar) {
+        diagnostics.extend(datastar::check(file));
+    }
+    if let Some(err) = &file.parse_error {
+        diagnostics.push(Diagnostic {
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("htmx-lsp".into()),
+            message: format!(
+                "Failed to parse document, results are for its last valid version: {err}"
+            ),
+            ..Default::default()
+        });
+    }
+    Some(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::lineindex::Encoding;
+
+    fn extension_warnings(html: &str) -> Vec<String> {
+        let store = TextStore::with_page(html);
+        let file = store.files.values().next().unwrap();
+        check_extensions(file, &[file])
+            .into_iter()
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn extension_attributes_need_the_extension() {
+        let warnings = extension_warnings(r#"<div sse-connect="/events"></div>"#);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("sse-connect does nothing without the `sse` extension"));
+        assert!(extension_warnings(r#"<div hx-ext="sse"><p sse-swap="m"></p></div>"#).is_empty());
+    }
+
+    #[test]
+    fn shared_names_are_not_reported_without_the_extension() {
+        assert!(extension_warnings(r#"<video preload="none"></video>"#).is_empty());
+        assert!(
+            extension_warnings(r#"<a href="/" preload="mouseover" classes="add x"></a>"#)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn out_of_band_swaps_need_a_target_in_the_requesting_page() {
+        let page = Uri::from_str("file:///page.html").unwrap();
+        let fragment = Uri::from_str("file:///rows.html").unwrap();
+        let store = TextStore::with_files([
+            (page.clone(), r#"<p id="count"></p><ul class="list"></ul>"#),
+            (
+                fragment.clone(),
+                r#"<span id="count" hx-swap-oob="true"></span><li hx-swap-oob="beforeend:.list"></li>
+<b id="total" hx-swap-oob="true"></b><i hx-swap-oob="true"></i>"#,
+            ),
+        ]);
+        let messages: Vec<String> = check_swap_oob(&store.files[&fragment], &[&store.files[&page]])
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "hx-swap-oob: no element matches `#total` in the pages requesting this fragment",
+                "hx-swap-oob: element has no id to swap it by",
+            ]
+        );
+        assert!(check_swap_oob(&store.files[&fragment], &[]).is_empty());
+    }
+
+    #[test]
+    fn requests_need_a_route_accepting_their_method() {
+        let mut routes = RouteIndex::new(Encoding::Utf16);
+        routes.update(
+            &Uri::from_str("file:///app.py").unwrap(),
+            "@app.route('/contacts', methods=['GET', 'POST'])\ndef contacts(): pass\n@app.route('/contacts/<int:id>')\ndef show(id): pass",
+        );
+        let store = TextStore::with_page(
+            r#"<button hx-post="/contacts"></button><button hx-delete="/contacts/1"></button>
+<a hx-get="/nope?q=1"></a><a hx-get="relative"></a>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let messages: Vec<String> = check_routes(file, &routes)
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "hx-delete: `/contacts/1` does not accept DELETE, only GET",
+                "hx-get: no route matches `/nope`",
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_must_pick_something_submitting_values() {
+        let store = TextStore::with_page(
+            r##"<div id="filters"><input name="q"></div><p id="title"></p>
+<button hx-get="/" hx-include="#filters, #title, [name='q'], #missing, closest form"></button>"##,
+        );
+        let file = store.files.values().next().unwrap();
+        let messages: Vec<String> = check_include(file, &[file])
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["hx-include: `#title` picks no form, named field or element containing one"]
+        );
+    }
+
+    #[test]
+    fn json_values_follow_the_script_policy() {
+        let store = TextStore::with_page(
+            r#"<p hx-vals='{"a": 1}'></p><p hx-vals='"b": 2'></p><p hx-headers="js:{a: b()}"></p><p hx-vals='[1]'></p>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let count = |policy| check_json(file, policy).len();
+        assert_eq!(count(JsPolicy::Allow), 1);
+        assert_eq!(count(JsPolicy::Warn), 2);
+        let severities: Vec<_> = check_json(file, JsPolicy::Error)
+            .into_iter()
+            .map(|d| d.severity)
+            .collect();
+        assert!(severities.contains(&Some(DiagnosticSeverity::ERROR)));
+    }
+
+    #[test]
+    fn selectors_resolve_across_composed_templates() {
+        let layout = Uri::from_str("file:///layout.html").unwrap();
+        let page = Uri::from_str("file:///index.html").unwrap();
+        let store = TextStore::with_files([
+            (layout.clone(), r#"<main id="main"></main>"#),
+            (
+                page.clone(),
+                r##"<a hx-target="#main"></a><a hx-target="#nope"></a><a hx-target="closest div"></a>"##,
+            ),
+        ]);
+        let file = &store.files[&page];
+        let messages: Vec<String> = check_selectors(file, &[file, &store.files[&layout]])
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(messages, ["hx-target: no element matches `#nope`"]);
+    }
+
+    #[test]
+    fn custom_attributes_follow_their_grammar() {
+        let mut library = LibraryIndex::default();
+        library.update(
+            &Uri::from_str("file:///custom.htmx.json").unwrap(),
+            r#"{ "attributes": [
+                { "name": "hx-track", "value": { "type": "enum", "values": ["click", "view"] } },
+                { "name": "hx-delay", "value": { "type": "pattern", "pattern": "\\d+ms" } },
+                { "name": "hx-flag", "value": { "type": "boolean" } }
+            ] }"#,
+        );
+        let store = TextStore::with_page(
+            r#"<p hx-track="hover" hx-delay="10ms" hx-flag="yes"></p><p hx-track="view" hx-delay="soon"></p>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        // Attributes of a tag aren't kept in order
+        let mut messages: Vec<String> = check_library(file, &[file], &library)
+            .into_iter()
+            .map(|d| d.message)
+            .collect();
+        messages.sort_unstable();
+        assert_eq!(
+            messages,
+            [
+                r"hx-delay: expected a value matching `\d+ms`, found `soon`",
+                "hx-flag: expected `true` or `false`, found `yes`",
+                "hx-track: expected `click` or `view`, found `hover`",
+            ]
+        );
+    }
+
+    #[test]
+    fn selector_lists_are_checked_item_by_item() {
+        let store = TextStore::with_page(
+            r##"<div id="filters"></div><button hx-include="#filters, #missing"></button>"##,
+        );
+        let file = store.files.values().next().unwrap();
+        let diagnostics = check_selectors(file, &[file]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "hx-include: no element matches `#missing`"
+        );
+        assert_eq!(diagnostics[0].range, file.lines.range(&file.source, 54..62));
+    }
+
+    #[test]
+    fn json_with_entities_is_decoded() {
+        let store = TextStore::with_page(
+            r#"<p hx-vals="{&quot;a&quot;: 1}"></p><p hx-headers="{&quot;b&quot;: x}"></p>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let diagnostics = check_json(file, JsPolicy::Warn);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0]
+            .message
+            .starts_with("hx-headers: invalid JSON"));
+        let x = file.source.rfind('x').unwrap();
+        assert_eq!(
+            diagnostics[0].range,
+            file.lines.range(&file.source, x..x + 1)
+        );
+    }
+}
@@ -0,0 +1,639 @@
+//! HTML embedded in other languages
+//!
+//! Each host language finds the regions of a document that contain HTML, along with the "holes" in
+//! those regions where the host language interpolates its own expressions. Everything outside of the
+//! regions and inside the holes is masked out, exactly like template tags, so the rest of the server
+//! can treat host documents as HTML without translating any offsets
+
+use std::ops::Range;
+
+use crate::template::{Dialect, Masked};
+
+/// HTML found in a host document
+#[derive(Default)]
+struct Regions {
+    /// Byte ranges containing HTML
+    html: Vec<Range<usize>>,
+    /// Byte ranges inside `html` holding host language expressions
+    holes: Vec<Range<usize>>,
+    /// Bytes replaced before masking, to turn markup that isn't HTML into tags
+    patches: Vec<(usize, u8)>,
+}
+
+impl Regions {
+    /// Masks everything outside of `self.html`, and everything inside `self.holes`
+    fn mask(mut self, src: &str) -> Masked {
+        self.html.sort_by_key(|r| r.start);
+        let mut spans = Vec::new();
+        let mut prev = 0;
+        for region in &self.html {
+            if prev < region.start {
+                spans.push(prev..region.start);
+            }
+            prev = prev.max(region.end);
+        }
+        if prev < src.len() {
+            spans.push(prev..src.len());
+        }
+        spans.extend(self.holes);
+        spans.sort_by_key(|r| r.start);
+        if self.patches.is_empty() {
+            return Masked::new(src, spans, Vec::new());
+        }
+        let mut patched = src.as_bytes().to_vec();
+        for (i, b) in self.patches {
+            patched[i] = b;
+        }
+        // Only ASCII delimiters and whitespace are replaced, with ASCII
+        let patched = String::from_utf8(patched).expect("Patching should preserve utf8");
+        Masked::new(&patched, spans, Vec::new())
+    }
+}
+
+/// Returns whether a string literal looks like it holds HTML
+fn looks_like_html(s: &str) -> bool {
+    s.as_bytes()
+        .windows(2)
+        .any(|w| w[0] == b'<' && (w[1].is_ascii_alphabetic() || w[1] == b'/'))
+}
+
+/// Returns the offset of the `quote` closing the string whose contents start at `start`
+fn string_end(bytes: &[u8], start: usize, quote: &[u8], escapes: bool) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        if bytes[i..].starts_with(quote) {
+            return i;
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Returns the offset of the bracket matching the one at `open`
+///
+/// Only brackets of the same kind are counted. Double quoted strings are skipped, but single quotes
+/// aren't, since they are just as likely to be apostrophes in the text of the embedded HTML
+fn matching(bytes: &[u8], open: usize) -> usize {
+    let (left, right) = match bytes[open] {
+        b'(' => (b'(', b')'),
+        b'[' => (b'[', b']'),
+        _ => (b'{', b'}'),
+    };
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => i = string_end(bytes, i + 1, b"\"", true),
+            c if c == left => depth += 1,
+            c if c == right => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Collects every `open ... }` pair in `range` as a hole, e.g. `{ expr }` in JSX or `${ expr }` in
+/// JavaScript template literals
+fn brace_holes(bytes: &[u8], range: Range<usize>, open: &[u8], holes: &mut Vec<Range<usize>>) {
+    let mut i = range.start;
+    while i < range.end {
+        if bytes[i..range.end].starts_with(open) {
+            let brace = i + open.len() - 1;
+            let end = (matching(bytes, brace) + 1).min(range.end);
+            holes.push(i..end);
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Python, where HTML lives in string literals. `{ expr }` in f-strings are holes
+#[derive(Debug)]
+pub struct Python;
+
+impl Dialect for Python {
+    fn mask(&self, src: &str) -> Masked {
+        let bytes = src.as_bytes();
+        let mut regions = Regions::default();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'#' => i = string_end(bytes, i, b"\n", false),
+                quote @ (b'"' | b'\'') => {
+                    let prefix_start = bytes[..i]
+                        .iter()
+                        .rposition(|c| !c.is_ascii_alphabetic())
+                        .map_or(0, |p| p + 1);
+                    let prefix = src[prefix_start..i].to_ascii_lowercase();
+                    let delim: &[u8] = if bytes[i..].starts_with(&[quote; 3]) {
+                        &bytes[i..i + 3]
+                    } else {
+                        &bytes[i..=i]
+                    };
+                    let start = i + delim.len();
+                    let end = string_end(bytes, start, delim, !prefix.contains('r'));
+                    if looks_like_html(&src[start..end]) {
+                        regions.html.push(start..end);
+                        if prefix.contains('f') {
+                            let mut holes = Vec::new();
+                            brace_holes(bytes, start..end, b"{", &mut holes);
+                            // `{{` is an escaped brace, not an expression
+                            regions.holes.extend(
+                                holes
+                                    .into_iter()
+                                    .filter(|h| !src[h.clone()].starts_with("{{")),
+                            );
+                        }
+                    }
+                    i = end + delim.len();
+                }
+                _ => i += 1,
+            }
+        }
+        regions.mask(src)
+    }
+}
+
+/// Rust, where HTML lives in string literals and in `html!`, `view!` and `rsx!` macros
+///
+/// `maud`'s `html!` doesn't use HTML syntax, so its elements are rewritten into flat tags, see
+/// [`maud`]
+#[derive(Debug)]
+pub struct Rust;
+
+impl Dialect for Rust {
+    fn mask(&self, src: &str) -> Masked {
+        let bytes = src.as_bytes();
+        let mut regions = Regions::default();
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            let hashes = rest.iter().skip(1).take_while(|&&c| c == b'#').count();
+            if rest.starts_with(b"//") {
+                i = string_end(bytes, i, b"\n", false);
+            } else if rest.starts_with(b"/*") {
+                i = string_end(bytes, i, b"*/", false) + 2;
+            } else if bytes[i] == b'"' {
+                let end = string_end(bytes, i + 1, b"\"", true);
+                if looks_like_html(&src[i + 1..end]) {
+                    regions.html.push(i + 1..end);
+                }
+                i = end + 1;
+            } else if bytes[i] == b'r'
+                && (i == 0 || !is_ident(bytes[i - 1]))
+                && rest.get(hashes + 1) == Some(&b'"')
+            {
+                // Raw strings, `r#"..."#`
+                let start = i + hashes + 2;
+                let close = format!("\"{}", "#".repeat(hashes));
+                let end = string_end(bytes, start, close.as_bytes(), false);
+                if looks_like_html(&src[start..end]) {
+                    regions.html.push(start..end);
+                }
+                i = end + close.len();
+            } else if bytes[i] == b'\'' {
+                // Char literals, but not lifetimes
+                i += match bytes.get(i + 1) {
+                    Some(b'\\') => string_end(bytes, i + 2, b"'", false) - i + 1,
+                    _ if bytes.get(i + 2) == Some(&b'\'') => 3,
+                    _ => 1,
+                };
+            } else if let Some(open) = macro_body(src, i) {
+                let close = matching(bytes, open);
+                let body = open + 1..close;
+                if src[body.clone()].trim_start().starts_with('<') {
+                    brace_holes(bytes, body.clone(), b"{", &mut regions.holes);
+                    regions.html.push(body);
+                } else if bytes[i..].starts_with(b"html") {
+                    maud(bytes, body, &mut regions);
+                }
+                i = close + 1;
+            } else {
+                i += 1;
+            }
+        }
+        regions.mask(src)
+    }
+}
+
+/// Returns the offset of the first non whitespace character at or after `i`
+fn skip_whitespace(bytes: &[u8], i: usize) -> usize {
+    i + bytes[i.min(bytes.len())..]
+        .iter()
+        .take_while(|c| c.is_ascii_whitespace())
+        .count()
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Turns the `maud` markup in `body` into HTML tags, in place
+///
+/// Each element becomes a tag of its own by reusing the whitespace before its name and the bytes
+/// around its `{` or `;`, so `button hx-get="/a" {` becomes `<button hx-get="/a"/>` and
+/// `input name="q";` becomes `<input name="q">`. Attributes keep their offsets, but nesting, text and
+/// the `.class` and `#id` shorthands are lost, and elements without whitespace before their name
+/// are skipped
+fn maud(bytes: &[u8], body: Range<usize>, regions: &mut Regions) {
+    let mut kept = Vec::new();
+    let mut i = body.start;
+    while i < body.end {
+        match bytes[i] {
+            b'"' => i = string_end(bytes, i + 1, b"\"", true) + 1,
+            b'(' | b'[' => i = matching(bytes, i) + 1,
+            b'@' => {
+                // Control flow, up to the block or `;` ending its header
+                i += 1;
+                while i < body.end && !matches!(bytes[i], b'{' | b';') {
+                    i = match bytes[i] {
+                        b'"' => string_end(bytes, i + 1, b"\"", true) + 1,
+                        b'(' | b'[' => matching(bytes, i) + 1,
+                        _ => i + 1,
+                    };
+                }
+            }
+            c if c.is_ascii_alphabetic() && !is_ident(bytes[i - 1]) => {
+                i = maud_element(bytes, i..body.end, &mut kept, &mut regions.patches);
+            }
+            _ => i += 1,
+        }
+    }
+    let mut prev = body.start;
+    for range in kept {
+        if prev < range.start {
+            regions.holes.push(prev..range.start);
+        }
+        prev = range.end;
+    }
+    if prev < body.end {
+        regions.holes.push(prev..body.end);
+    }
+    regions.html.push(body);
+}
+
+/// Rewrites the `maud` element whose name starts at `range.start` into a tag, collecting the bytes
+/// to keep visible. Returns the offset to resume scanning from
+fn maud_element(
+    bytes: &[u8],
+    range: Range<usize>,
+    kept: &mut Vec<Range<usize>>,
+    patches: &mut Vec<(usize, u8)>,
+) -> usize {
+    let name_len = |i: usize| {
+        bytes[i..range.end]
+            .iter()
+            .take_while(|&&c| is_ident(c) || matches!(c, b'-' | b':'))
+            .count()
+    };
+    let start = range.start;
+    let name = start + name_len(start);
+    let mut visible = Vec::new();
+    let mut i = name;
+    loop {
+        i = skip_whitespace(bytes, i).min(range.end);
+        match bytes.get(i) {
+            Some(b'{' | b';') if i < range.end => break,
+            Some(b'.' | b'#') => {
+                // `.class` and `#id` shorthands
+                i += 1;
+                i = match bytes.get(i) {
+                    Some(b'"') => string_end(bytes, i + 1, b"\"", true) + 1,
+                    Some(b'(') => matching(bytes, i) + 1,
+                    _ => i + name_len(i),
+                };
+            }
+            Some(c) if c.is_ascii_alphabetic() && i < range.end => {
+                let attr = i;
+                i += name_len(i);
+                match (bytes.get(i), bytes.get(i + 1)) {
+                    (Some(b'='), Some(b'"')) => {
+                        i = string_end(bytes, i + 2, b"\"", true) + 1;
+                        visible.push(attr..i);
+                    }
+                    // `name=(expr)` and `name=[optional]`
+                    (Some(b'='), Some(b'(' | b'[')) => {
+                        visible.push(attr..i + 1);
+                        i = matching(bytes, i + 1) + 1;
+                    }
+                    (Some(b'='), _) => return i,
+                    // `name[toggle]`
+                    (Some(b'['), _) => {
+                        visible.push(attr..i);
+                        i = matching(bytes, i) + 1;
+                    }
+                    _ => visible.push(attr..i),
+                }
+            }
+            _ => return i,
+        }
+    }
+    if !matches!(bytes[start - 1], b' ' | b'\t') {
+        return i + 1;
+    }
+    patches.push((start - 1, b'<'));
+    visible.push(start - 1..name);
+    if bytes[i] == b'{' && matches!(bytes[i - 1], b' ' | b'\t') {
+        patches.push((i - 1, b'/'));
+        visible.push(i - 1..i);
+    }
+    patches.push((i, b'>'));
+    visible.push(i..i + 1);
+    visible.sort_by_key(|r| r.start);
+    kept.extend(visible);
+    i + 1
+}
+
+/// If a `html!`, `view!` or `rsx!` macro is invoked at `i`, returns the offset of its opening bracket
+fn macro_body(src: &str, i: usize) -> Option<usize> {
+    let bytes = src.as_bytes();
+    if i > 0 && is_ident(bytes[i - 1]) {
+        return None;
+    }
+    let name = [b"html".as_slice(), b"view", b"rsx"]
+        .into_iter()
+        .find(|name| bytes[i..].starts_with(name))?;
+    let bang = skip_whitespace(bytes, i + name.len());
+    if bytes.get(bang) != Some(&b'!') {
+        return None;
+    }
+    let open = skip_whitespace(bytes, bang + 1);
+    matches!(bytes.get(open), Some(b'{' | b'(' | b'[')).then_some(open)
+}
+
+/// JavaScript and TypeScript, including JSX
+///
+/// HTML lives in string literals, template literals (with `${ expr }` holes) and in parenthesized JSX
+/// such as `return (<div>...</div>)` (with `{ expr }` holes)
+#[derive(Debug)]
+pub struct JavaScript;
+
+impl Dialect for JavaScript {
+    fn mask(&self, src: &str) -> Masked {
+        let bytes = src.as_bytes();
+        let mut regions = Regions::default();
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            if rest.starts_with(b"//") {
+                i = string_end(bytes, i, b"\n", false);
+            } else if rest.starts_with(b"/*") {
+                i = string_end(bytes, i, b"*/", false) + 2;
+            } else if let quote @ (b'"' | b'\'' | b'`') = bytes[i] {
+                let end = if quote == b'`' {
+                    template_literal_end(bytes, i + 1)
+                } else {
+                    string_end(bytes, i + 1, &[quote], true)
+                };
+                if looks_like_html(&src[i + 1..end]) {
+                    if quote == b'`' {
+                        brace_holes(bytes, i + 1..end, b"${", &mut regions.holes);
+                    }
+                    regions.html.push(i + 1..end);
+                }
+                i = end + 1;
+            } else if bytes[i] == b'(' && bytes.get(skip_whitespace(bytes, i + 1)) == Some(&b'<') {
+                let close = matching(bytes, i);
+                brace_holes(bytes, i + 1..close, b"{", &mut regions.holes);
+                regions.html.push(i + 1..close);
+                i = close + 1;
+            } else {
+                i += 1;
+            }
+        }
+        regions.mask(src)
+    }
+}
+
+/// Returns the offset of the backtick closing the template literal whose contents start at `start`
+fn template_literal_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'`' => return i,
+            b'$' if bytes.get(i + 1) == Some(&b'{') => i = matching(bytes, i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// `templ`, where HTML lives in the bodies of `templ Name() { ... }` components
+///
+/// Inside components, `{ expr }` interpolations, Go control flow such as `if cond {` and `} else {`, and
+/// `@component()` calls are holes
+#[derive(Debug)]
+pub struct Templ;
+
+/// Go statements that open a block in a `templ` component
+const TEMPL_STATEMENTS: &[&str] = &["if ", "for ", "switch ", "else", "}"];
+
+impl Templ {
+    /// Collects the holes in the component body `body`
+    fn holes(src: &str, body: Range<usize>, holes: &mut Vec<Range<usize>>) {
+        let bytes = src.as_bytes();
+        let mut i = body.start;
+        while i < body.end {
+            let line_start = bytes[..i]
+                .iter()
+                .rposition(|&c| c == b'\n')
+                .map_or(0, |p| p + 1)
+                .max(body.start);
+            let line = bytes[line_start..i].trim_ascii_start();
+            let rest = &bytes[i..];
+            match bytes[i] {
+                b'{' if TEMPL_STATEMENTS
+                    .iter()
+                    .any(|s| line.starts_with(s.as_bytes())) =>
+                {
+                    holes.push(line_start..i + 1);
+                    i += 1;
+                }
+                b'{' => {
+                    let end = (matching(bytes, i) + 1).min(body.end);
+                    holes.push(i..end);
+                    i = end;
+                }
+                // Closes a Go block, since interpolations are skipped as a whole
+                b'}' => {
+                    holes.push(i..i + 1);
+                    i += 1;
+                }
+                b'@' if line.is_empty() => {
+                    let end = string_end(bytes, i, b"\n", false).min(body.end);
+                    holes.push(i..end);
+                    i = end;
+                }
+                _ if line.is_empty()
+                    && (rest.starts_with(b"case ") || rest.starts_with(b"default:")) =>
+                {
+                    let end = string_end(bytes, i, b"\n", false).min(body.end);
+                    holes.push(i..end);
+                    i = end;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+}
+
+impl Dialect for Templ {
+    fn mask(&self, src: &str) -> Masked {
+        let bytes = src.as_bytes();
+        let mut regions = Regions::default();
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &bytes[i..];
+            let at_line_start = i == 0 || bytes[i - 1] == b'\n';
+            if rest.starts_with(b"//") {
+                i = string_end(bytes, i, b"\n", false);
+            } else if rest.starts_with(b"/*") {
+                i = string_end(bytes, i, b"*/", false) + 2;
+            } else if bytes[i] == b'"' {
+                i = string_end(bytes, i + 1, b"\"", true) + 1;
+            } else if bytes[i] == b'`' {
+                i = string_end(bytes, i + 1, b"`", false) + 1;
+            } else if at_line_start && rest.starts_with(b"templ ") {
+                // The body starts after the parameter list, which may contain braces of its own
+                let params = rest
+                    .iter()
+                    .position(|&c| c == b'(')
+                    .map_or(i, |p| matching(bytes, i + p));
+                let Some(open) = bytes[params..]
+                    .iter()
+                    .position(|&c| c == b'{')
+                    .map(|p| params + p)
+                else {
+                    break;
+                };
+                let close = matching(bytes, open);
+                let body = open + 1..close;
+                Self::holes(src, body.clone(), &mut regions.holes);
+                regions.html.push(body);
+                i = close + 1;
+            } else {
+                i += 1;
+            }
+        }
+        regions.mask(src)
+    }
+}
+
+/// Picks the host language of a non-HTML document from its `languageId`, falling back to its file
+/// extension
+pub fn host_for(
+    extension: Option<&str>,
+    language_id: Option<&str>,
+) -> Option<&'static dyn Dialect> {
+    match (language_id, extension) {
+        (Some("python"), _) | (None, Some("py")) => Some(&Python),
+        (Some("rust"), _) | (None, Some("rs")) => Some(&Rust),
+        (Some("javascript" | "javascriptreact" | "typescript" | "typescriptreact"), _)
+        | (None, Some("js" | "jsx" | "ts" | "tsx" | "mjs")) => Some(&JavaScript),
+        (Some("templ"), _) | (None, Some("templ")) => Some(&Templ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the HTML left visible in `src`, with runs of whitespace collapsed
+    fn visible(dialect: &dyn Dialect, src: &str) -> String {
+        let masked = dialect.mask(src);
+        assert_eq!(masked.text.len(), src.len());
+        masked.text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn python_strings_and_f_string_holes() {
+        let src = r#"x = "no html"
+# "<p>comment</p>"
+page = f'<p id="a">{name}</p>{{literal}}'
+raw = r"<b>\n</b>""#;
+        assert_eq!(
+            visible(&Python, src),
+            r#"<p id="a"> </p>{{literal}} <b>\n</b>"#
+        );
+    }
+
+    #[test]
+    fn python_triple_quoted_strings() {
+        let src = "t = \"\"\"\n<div class=\"x\">it's</div>\n\"\"\"";
+        assert_eq!(visible(&Python, src), "<div class=\"x\">it's</div>");
+    }
+
+    #[test]
+    fn rust_strings_and_macros() {
+        let src = r##"let a = "<p>a</p>"; let b = r#"<b class="x">b</b>"#;
+let c = 'x'; fn f<'a>() {}
+view! { <div class="y">{ count }</div> }
+html! { p.note { "maud" } }"##;
+        assert_eq!(
+            visible(&Rust, src),
+            r#"<p>a</p> <b class="x">b</b> <div class="y"> </div> <p />"#
+        );
+    }
+
+    #[test]
+    fn rust_maud_attributes() {
+        let src = r##"html! {
+    form#search hx-post="/search" hx-vals=(vals) {
+        @if let Some(q) = query { "Searching " (q) }
+        input name="q" autofocus required[strict];
+        button.primary hx-target="#results" { "Go" }
+    }
+}"##;
+        assert_eq!(
+            visible(&Rust, src),
+            r##"<form hx-post="/search" hx-vals=" "/> <input name="q" autofocus required > <button hx-target="#results"/>"##
+        );
+    }
+
+    #[test]
+    fn javascript_literals_and_jsx() {
+        let src = r#"const a = `<li>${item.name}</li>`;
+// "<p>comment</p>"
+return (<div className="x">{label}</div>);"#;
+        assert_eq!(
+            visible(&JavaScript, src),
+            r#"<li> </li> <div className="x"> </div>"#
+        );
+    }
+
+    #[test]
+    fn templ_component_bodies() {
+        let src = r#"package main
+
+templ Hello(name string) {
+	if name != "" {
+		<p class="greeting">{ name }</p>
+	} else {
+		@Anonymous()
+	}
+}"#;
+        assert_eq!(visible(&Templ, src), r#"<p class="greeting"> </p>"#);
+    }
+
+    #[test]
+    fn picks_hosts_by_language_then_extension() {
+        assert!(host_for(Some("md"), Some("python")).is_some());
+        assert!(host_for(Some("tsx"), None).is_some());
+        assert!(host_for(Some("py"), Some("markdown")).is_none());
+        assert!(host_for(Some("html"), None).is_none());
+    }
+}
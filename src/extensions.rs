@@ -0,0 +1,432 @@
+//! The official htmx extensions, which are enabled on an element and its children with `hx-ext`
+
+use std::collections::HashSet;
+
+use phf::phf_map;
+
+use lsp_types::Uri;
+
+use crate::{
+    textstore::{attribute, FileData, TextStore},
+    workspace::TemplateGraph,
+};
+
+#[allow(clippy::needless_raw_string_hashes)]
+pub static EXTENSIONS: phf::Map<&'static str, &'static str> = phf_map! {
+    "sse" =>
+r###"connects to a Server Sent Event source and swaps in the messages it sends
+
+```html
+<div hx-ext="sse" sse-connect="/chatroom" sse-swap="message"></div>
+```
+
+Elements inside can use `sse-swap` to swap in events by name, and `hx-trigger="sse:<event>"` to issue
+requests when an event is received. `sse-close` names an event that closes the connection.
+
+[Documentation](https://htmx.org/extensions/sse/)"###,
+    "ws" =>
+r###"connects to a WebSocket, swapping in the messages it receives and sending the values of forms in it
+
+```html
+<div hx-ext="ws" ws-connect="/chatroom">
+  <form ws-send>
+    <input name="message">
+  </form>
+</div>
+```
+
+Messages received are swapped out of band by the ids of their top level elements.
+
+[Documentation](https://htmx.org/extensions/ws/)"###,
+    "json-enc" =>
+r###"encodes the parameters of requests as a JSON object instead of a form
+
+```html
+<form hx-post="/contacts" hx-ext="json-enc"></form>
+```
+
+[Documentation](https://htmx.org/extensions/json-enc/)"###,
+    "preload" =>
+r###"loads the targets of links before they are clicked, so the content is there when it is needed
+
+```html
+<body hx-ext="preload">
+  <a href="/contacts" preload="mouseover">Contacts</a>
+</body>
+```
+
+`preload` takes the event that triggers the request, `mousedown` by default.
+
+[Documentation](https://htmx.org/extensions/preload/)"###,
+    "response-targets" =>
+r###"picks the target to swap error responses into by their status code
+
+```html
+<div hx-ext="response-targets">
+  <button hx-post="/register" hx-target="#response" hx-target-5*="#serious-errors">Register</button>
+</div>
+```
+
+`hx-target-*`, `hx-target-4*` or `hx-target-404` apply to the matching status codes, and
+`hx-target-error` to all 4xx and 5xx responses.
+
+[Documentation](https://htmx.org/extensions/response-targets/)"###,
+    "head-support" =>
+r###"merges the `<head>` of responses into the document's, instead of ignoring it
+
+```html
+<body hx-ext="head-support"></body>
+```
+
+Elements in the response's head can opt out of merging with `hx-head="re-eval"` or
+`hx-preserve="true"`.
+
+[Documentation](https://htmx.org/extensions/head-support/)"###,
+    "idiomorph" =>
+r###"swaps content by morphing the existing DOM into the response, keeping focus and state
+
+```html
+<div hx-ext="morph">
+  <button hx-get="/example" hx-swap="morph">Morph</button>
+</div>
+```
+
+Adds the `morph`, `morph:outerHTML` and `morph:innerHTML` swap styles. The extension registers itself
+as `morph`.
+
+[Documentation](https://htmx.org/extensions/idiomorph/)"###,
+    "morph" =>
+r###"swaps content by morphing the existing DOM into the response, keeping focus and state
+
+The name the [idiomorph](https://htmx.org/extensions/idiomorph/) extension registers itself with.
+Adds the `morph`, `morph:outerHTML` and `morph:innerHTML` swap styles."###,
+    "loading-states" =>
+r###"shows loading states on elements while a request is in flight
+
+```html
+<body hx-ext="loading-states">
+  <button hx-post="/save" data-loading-disable>Save</button>
+</body>
+```
+
+Uses `data-loading`, `data-loading-class`, `data-loading-disable`, `data-loading-aria-busy` and the
+other `data-loading-*` attributes.
+
+[Documentation](https://htmx.org/extensions/loading-states/)"###,
+    "class-tools" =>
+r###"adds, removes or toggles classes over time
+
+```html
+<div hx-ext="class-tools">
+  <div classes="add foo:1s, remove foo:2s">...</div>
+</div>
+```
+
+`classes` takes a comma separated list of `add`, `remove` or `toggle` operations with their delays,
+and `&` separates runs that happen in parallel.
+
+[Documentation](https://htmx.org/extensions/class-tools/)"###,
+    "multi-swap" =>
+r###"swaps several elements of the response by id
+
+```html
+<body hx-ext="multi-swap">
+  <button hx-get="/example" hx-swap="multi:#id1,#id2:outerHTML">Swap</button>
+</body>
+```
+
+[Documentation](https://htmx.org/extensions/multi-swap/)"###,
+    "path-deps" =>
+r###"refreshes elements when requests are made to paths they depend on
+
+```html
+<div hx-ext="path-deps" hx-get="/example" hx-trigger="path-deps" path-deps="/contacts">...</div>
+```
+
+[Documentation](https://htmx.org/extensions/path-deps/)"###,
+    "remove-me" =>
+r###"removes elements after a delay
+
+```html
+<div hx-ext="remove-me">
+  <div remove-me="1s">Saved!</div>
+</div>
+```
+
+[Documentation](https://htmx.org/extensions/remove-me/)"###,
+};
+
+/// An attribute that only does something when the extension adding it is enabled
+pub struct Attribute {
+    pub name: &'static str,
+    pub extension: &'static str,
+    pub description: &'static str,
+}
+
+/// Extension attributes whose names are also standard attributes, like `preload` on `<audio>` and
+/// `<video>`, or are common outside htmx, so using them without the extension isn't a mistake
+pub static SHARED_NAMES: &[&str] = &["preload", "classes"];
+
+pub static ATTRIBUTES: &[Attribute] = &[
+    Attribute {
+        name: "sse-connect",
+        extension: "sse",
+        description: "the url of the Server Sent Event source to connect to",
+    },
+    Attribute {
+        name: "sse-swap",
+        extension: "sse",
+        description: "the names of the events to swap into this element, separated by commas",
+    },
+    Attribute {
+        name: "sse-close",
+        extension: "sse",
+        description: "the name of the event that closes the connection",
+    },
+    Attribute {
+        name: "ws-connect",
+        extension: "ws",
+        description: "the url of the WebSocket to connect to",
+    },
+    Attribute {
+        name: "ws-send",
+        extension: "ws",
+        description: "sends the values of this element to the closest WebSocket when it is triggered",
+    },
+    Attribute {
+        name: "hx-target-404",
+        extension: "response-targets",
+        description: "the target to swap 404 responses into. Any other status code works too, and `*` or \
+            `x` stand for any digit, as in `hx-target-5*`",
+    },
+    Attribute {
+        name: "hx-target-4*",
+        extension: "response-targets",
+        description: "the target to swap 4xx responses into",
+    },
+    Attribute {
+        name: "hx-target-5*",
+        extension: "response-targets",
+        description: "the target to swap 5xx responses into",
+    },
+    Attribute {
+        name: "hx-target-error",
+        extension: "response-targets",
+        description: "the target to swap 4xx and 5xx responses into",
+    },
+    Attribute {
+        name: "preload",
+        extension: "preload",
+        description: "loads the target of this link ahead of time on `mousedown`, `mouseover` or `init`",
+    },
+    Attribute {
+        name: "preload-images",
+        extension: "preload",
+        description: "also loads the images in preloaded content",
+    },
+    Attribute {
+        name: "data-loading",
+        extension: "loading-states",
+        description: "shows this element while a request is in flight",
+    },
+    Attribute {
+        name: "data-loading-class",
+        extension: "loading-states",
+        description: "classes to add while a request is in flight",
+    },
+    Attribute {
+        name: "data-loading-class-remove",
+        extension: "loading-states",
+        description: "classes to remove while a request is in flight",
+    },
+    Attribute {
+        name: "data-loading-disable",
+        extension: "loading-states",
+        description: "disables this element while a request is in flight",
+    },
+    Attribute {
+        name: "data-loading-aria-busy",
+        extension: "loading-states",
+        description: "sets `aria-busy` on this element while a request is in flight",
+    },
+    Attribute {
+        name: "data-loading-delay",
+        extension: "loading-states",
+        description: "waits this long before applying loading states, `200ms` by default",
+    },
+    Attribute {
+        name: "data-loading-target",
+        extension: "loading-states",
+        description: "applies the loading states to the elements matching this selector instead",
+    },
+    Attribute {
+        name: "data-loading-path",
+        extension: "loading-states",
+        description: "only applies loading states to requests to this path",
+    },
+    Attribute {
+        name: "data-loading-states",
+        extension: "loading-states",
+        description: "scopes loading states to the requests of elements inside this one",
+    },
+    Attribute {
+        name: "classes",
+        extension: "class-tools",
+        description: "a comma separated list of `add`, `remove` or `toggle` operations on classes with their \
+            delays, with `&` separating runs that happen in parallel",
+    },
+    Attribute {
+        name: "path-deps",
+        extension: "path-deps",
+        description: "refreshes this element when a request is made to this path",
+    },
+    Attribute {
+        name: "remove-me",
+        extension: "remove-me",
+        description: "removes this element after this delay",
+    },
+];
+
+/// The `hx-target-*` attributes of `response-targets` for status codes that aren't in [`ATTRIBUTES`],
+/// which only lists examples to complete
+static STATUS_TARGET: Attribute = Attribute {
+    name: "hx-target-*",
+    extension: "response-targets",
+    description:
+        "the target to swap responses into when their status code matches, where `*` or `x` \
+        stand for any digit, as in `hx-target-404` or `hx-target-5*`",
+};
+
+/// Returns the extension attribute named `name`
+///
+/// Names like `hx-target-503` are matched against the `hx-target-*` pattern of `response-targets`
+pub fn lookup(name: &str) -> Option<&'static Attribute> {
+    let name = name.to_ascii_lowercase();
+    ATTRIBUTES
+        .iter()
+        .find(|attribute| attribute.name == name)
+        .or_else(|| {
+            let code = name.strip_prefix("hx-target-")?;
+            let status = code.len() <= 3
+                && code
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == 'x' || c == '*');
+            status.then_some(&STATUS_TARGET)
+        })
+}
+
+/// Returns the extensions enabled on the tag at index `i` by `hx-ext` on it or its ancestors, leaving out
+/// the ones a closer `ignore:` turns off
+pub fn inherited(file: &FileData, i: usize) -> HashSet<&str> {
+    let mut enabled = HashSet::new();
+    let mut ignored = HashSet::new();
+    for i in file.ancestors(i) {
+        let Some(value) = file
+            .dom
+            .nodes()
+            .get(i)
+            .and_then(tl::Node::as_tag)
+            .and_then(|tag| attribute(tag, "hx-ext"))
+        else {
+            continue;
+        };
+        for name in value.split(',').map(str::trim) {
+            match name.strip_prefix("ignore:") {
+                Some(name) => {
+                    ignored.insert(name.trim());
+                }
+                None if !ignored.contains(name) => {
+                    enabled.insert(name);
+                }
+                None => {}
+            }
+        }
+    }
+    enabled
+}
+
+/// Returns the extensions enabled anywhere in `files`, which are the layouts and pages a file is part of
+///
+/// Which of their elements a file ends up inside isn't known, so any `hx-ext` in them counts
+pub fn declared<'a>(files: &[&'a FileData]) -> HashSet<&'a str> {
+    files
+        .iter()
+        .flat_map(|file| file.attributes())
+        .filter(|(_, key, _)| key.eq_ignore_ascii_case("hx-ext"))
+        .flat_map(|(_, _, value)| value.split(',').map(str::trim))
+        .filter(|name| !name.starts_with("ignore:"))
+        .collect()
+}
+
+/// Returns the extensions enabled on the tag at index `i` of the file at `uri`, either by its ancestors
+/// or by the layouts and pages the file is part of
+pub fn enabled<'a>(
+    textstore: &'a TextStore,
+    graph: &TemplateGraph,
+    uri: &Uri,
+    i: usize,
+) -> HashSet<&'a str> {
+    let Some(file) = textstore.files.get(uri) else {
+        return HashSet::new();
+    };
+    let layouts: Vec<&FileData> = graph
+        .composed(uri)
+        .into_iter()
+        .filter(|other| *other != uri)
+        .filter_map(|uri| textstore.files.get(uri))
+        .collect();
+    let mut enabled = inherited(file, i);
+    enabled.extend(declared(&layouts));
+    enabled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_attributes_and_status_code_targets() {
+        assert_eq!(lookup("SSE-Connect").unwrap().extension, "sse");
+        assert_eq!(lookup("hx-target-404").unwrap().name, "hx-target-404");
+        assert_eq!(lookup("hx-target-503").unwrap().name, "hx-target-*");
+        assert_eq!(lookup("hx-target-5xx").unwrap().name, "hx-target-*");
+        assert_eq!(lookup("hx-target-4*").unwrap().name, "hx-target-4*");
+        assert!(lookup("hx-target-1234").is_none());
+        assert!(lookup("hx-target").is_none());
+    }
+
+    #[test]
+    fn completes_concrete_status_code_targets() {
+        let targets: Vec<&str> = ATTRIBUTES
+            .iter()
+            .map(|attribute| attribute.name)
+            .filter(|name| name.starts_with("hx-target-"))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                "hx-target-404",
+                "hx-target-4*",
+                "hx-target-5*",
+                "hx-target-error"
+            ]
+        );
+    }
+
+    #[test]
+    fn closer_ignores_turn_off_inherited_extensions() {
+        let html = r#"<body hx-ext="sse, preload"><div hx-ext="ignore:sse, ws"><p id="p"></p></div></body>"#;
+        let store = TextStore::with_page(html);
+        let file = store.files.values().next().unwrap();
+        let (i, _) = file
+            .tags()
+            .find(|(_, tag)| attribute(tag, "id") == Some("p"))
+            .unwrap();
+        let mut enabled: Vec<&str> = inherited(file, i).into_iter().collect();
+        enabled.sort_unstable();
+        assert_eq!(enabled, ["preload", "ws"]);
+        let mut declared: Vec<&str> = declared(&[file]).into_iter().collect();
+        declared.sort_unstable();
+        assert_eq!(declared, ["preload", "sse", "ws"]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use lsp_types::Uri;
+
+use crate::{
+    config::Config,
+    routes::{requests, specificity, url_matches},
+    textstore::TextStore,
+    workspace::TemplateGraph,
+};
+
+/// Returns the name of the template configured as the response to requests to `url`
+///
+/// When several routes match, the most specific one wins, and the first in lexical order between
+/// routes that are just as specific
+pub fn fragment_for<'a>(config: &'a Config, url: &str) -> Option<&'a str> {
+    if url.trim().is_empty() {
+        return None;
+    }
+    config
+        .fragments
+        .iter()
+        .filter(|(route, _)| url_matches(route, url))
+        .max_by(|(a, _), (b, _)| specificity(a).cmp(&specificity(b)).then_with(|| b.cmp(a)))
+        .map(|(_, template)| template.as_str())
+}
+
+/// Which templates request which fragments
+pub struct RequestGraph<'a> {
+    /// Pairs of a template issuing a request and the fragment template rendered in response
+    edges: Vec<(&'a Uri, &'a Uri)>,
+}
+
+impl<'a> RequestGraph<'a> {
+    pub fn new(textstore: &'a TextStore, graph: &'a TemplateGraph, config: &Config) -> Self {
+        let mut edges = Vec::new();
+        for (uri, file) in &textstore.files {
+            for request in requests(file) {
+                let Some(name) = fragment_for(config, request.url) else {
+                    continue;
+                };
+                if let Some(fragment) = graph.resolve(name, None) {
+                    if !edges.contains(&(uri, fragment)) {
+                        edges.push((uri, fragment));
+                    }
+                }
+            }
+        }
+        Self { edges }
+    }
+
+    /// Returns every template making up a page that content from `uri` may be swapped into, as part of
+    /// a response to a request
+    ///
+    /// The templates rendered in the response are left out, even when the page also renders them, since
+    /// the content swapped in can't be its own target
+    pub fn receivers(&self, uri: &Uri, graph: &'a TemplateGraph) -> HashSet<&'a Uri> {
+        self.edges
+            .iter()
+            .filter_map(|(requester, fragment)| {
+                let rendered = graph.rendered(fragment);
+                rendered.contains(uri).then(|| {
+                    graph
+                        .composed(requester)
+                        .into_iter()
+                        .filter(move |receiver| !rendered.contains(receiver))
+                })
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(routes: &[(&str, &str)]) -> Config {
+        Config {
+            fragments: routes
+                .iter()
+                .map(|(route, template)| ((*route).to_string(), (*template).to_string()))
+                .collect(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn most_specific_route_wins() {
+        let config = config(&[
+            ("/contacts/<id>", "contact.html"),
+            ("/contacts/count", "count.html"),
+            ("/<kind>/1", "kind.html"),
+        ]);
+        assert_eq!(fragment_for(&config, "/contacts/count"), Some("count.html"));
+        assert_eq!(fragment_for(&config, "/contacts/1"), Some("contact.html"));
+        assert_eq!(fragment_for(&config, "/archive/1"), Some("kind.html"));
+        assert_eq!(fragment_for(&config, "/"), None);
+        assert_eq!(fragment_for(&config, " "), None);
+    }
+
+    #[test]
+    fn ties_are_broken_in_lexical_order() {
+        let config = config(&[("/contacts/{id}", "b.html"), ("/contacts/<id>", "a.html")]);
+        for _ in 0..10 {
+            assert_eq!(fragment_for(&config, "/contacts/1"), Some("a.html"));
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! _hyperscript, a scripting language written in the `_` attribute that is often used alongside htmx
+
+use lsp_types::{CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind};
+use phf::phf_map;
+
+use crate::{textstore::FileData, values::completion};
+
+pub static ATTRIBUTES: &[&str] = &["_", "script", "data-script"];
+
+#[allow(clippy::needless_raw_string_hashes)]
+pub static DESCRIPTIONS: phf::Map<&'static str, &'static str> = phf_map! {
+    "_" =>
+r###"attaches _hyperscript to the element
+
+```html
+<button _="on click toggle .red on me">Click Me</button>
+```
+
+Also written as `script` or `data-script`.
+
+[Documentation](https://hyperscript.org/docs/)"###,
+    "script" =>
+r###"attaches _hyperscript to the element, the same as `_`
+
+[Documentation](https://hyperscript.org/docs/)"###,
+    "data-script" =>
+r###"attaches _hyperscript to the element, the same as `_`
+
+[Documentation](https://hyperscript.org/docs/)"###,
+};
+
+/// The commands and features of the language, with their syntax and a description
+pub static COMMANDS: phf::Map<&'static str, (&'static str, &'static str)> = phf_map! {
+    "on" => ("on <event> [from <target>] <commands> end", "handles an event, dispatched on the element or on another one with `from`"),
+    "init" => ("init <commands> end", "runs commands when the element is initialized"),
+    "def" => ("def <name>(<args>) <commands> end", "defines a function"),
+    "behavior" => ("behavior <Name>(<args>) <features> end", "defines a reusable set of features, added to elements with `install`"),
+    "install" => ("install <Behavior>", "adds a behavior to the element"),
+    "send" => ("send <event>(<args>) [to <target>]", "dispatches an event on the element or a target"),
+    "trigger" => ("trigger <event>(<args>) [on <target>]", "dispatches an event on the element or a target"),
+    "toggle" => ("toggle .class [on <target>] [for <duration>]", "toggles a class or attribute, or the visibility of the target"),
+    "add" => ("add .class to <target>", "adds a class, attribute or style to the element or a target"),
+    "remove" => ("remove .class from <target>", "removes a class or attribute from a target, or the target itself"),
+    "put" => ("put <value> into <target>", "inserts content into, before or after a target, or sets a property"),
+    "set" => ("set <variable> to <value>", "sets a variable or property"),
+    "increment" => ("increment <variable> [by <amount>]", "increases a number"),
+    "decrement" => ("decrement <variable> [by <amount>]", "decreases a number"),
+    "wait" => ("wait <duration> | wait for <event>", "pauses for a duration, such as `2s`, or until an event is dispatched"),
+    "settle" => ("settle [<target>]", "waits for the transitions of a target to end"),
+    "transition" => ("transition <property> to <value> [over <duration>]", "animates styles of the element or a target"),
+    "show" => ("show <target> [with <strategy>]", "shows the element or a target"),
+    "hide" => ("hide <target> [with <strategy>]", "hides the element or a target"),
+    "take" => ("take .class from <elements> [for <target>]", "removes a class from elements and adds it to the element or a target"),
+    "tell" => ("tell <target> <commands> end", "runs commands with `you` bound to a target"),
+    "call" => ("call <expression>", "evaluates an expression, storing the result in `it`"),
+    "get" => ("get <expression>", "evaluates an expression, storing the result in `it`"),
+    "fetch" => ("fetch <url> [as json|html|text]", "issues a request, storing the response in `it`"),
+    "go" => ("go to <url> | go back", "navigates to a url or back in history"),
+    "log" => ("log <value>", "logs values to the console"),
+    "if" => ("if <condition> <commands> [else <commands>] end", "runs commands when a condition holds"),
+    "repeat" => ("repeat <n> times | for <x> in <list> | while <condition> ... end", "runs commands in a loop"),
+    "halt" => ("halt [the event]", "stops the event from propagating and its default action, and the handler"),
+    "return" => ("return <value>", "returns a value from a function"),
+    "throw" => ("throw <value>", "throws an exception"),
+    "js" => ("js(<args>) <javascript> end", "runs inline JavaScript"),
+    "append" => ("append <value> to <target>", "appends a value to a string, array or element"),
+    "make" => ("make a <Class>", "creates an object or element"),
+    "then" => ("<command> then <command>", "separates commands"),
+    "end" => ("end", "ends a block"),
+};
+
+/// Returns the bounds of the word around `cursor` in `value`
+fn word_at(value: &str, cursor: usize) -> (usize, usize) {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let start = value[..cursor]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |i| i + 1);
+    let end = value[cursor..]
+        .find(|c: char| !is_word(c))
+        .map_or(value.len(), |i| cursor + i);
+    (start, end)
+}
+
+/// Documents a command with its syntax
+fn document(syntax: &str, description: &str) -> String {
+    format!("```\n{syntax}\n```\n\n{description}")
+}
+
+/// Completes the command under `cursor` in a script
+///
+/// Class, id and attribute references like `.red` or `#output` aren't completed
+pub fn completions(file: &FileData, value: &str, cursor: usize) -> Vec<CompletionItem> {
+    let (start, end) = word_at(value, cursor);
+    if value[..start].ends_with(['.', '#', '@', '$', '<', ':']) {
+        return Vec::new();
+    }
+    let range = file.range_of(&value[start..end]);
+    COMMANDS
+        .entries()
+        .map(|(name, (syntax, description))| CompletionItem {
+            detail: Some((*syntax).to_string()),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: document(syntax, description),
+            })),
+            ..completion(name, CompletionItemKind::KEYWORD, range)
+        })
+        .collect()
+}
+
+/// Describes the command under `cursor` in a script, returning it and its description
+pub fn describe(value: &str, cursor: usize) -> Option<(&str, String)> {
+    let (start, end) = word_at(value, cursor);
+    if value[..start].ends_with(['.', '#', '@', '$', '<', ':']) {
+        return None;
+    }
+    let name = &value[start..end];
+    let (syntax, description) = COMMANDS.get(name)?;
+    Some((name, document(syntax, description)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_word_under_the_cursor() {
+        let value = "on click toggle .is-open on #menu";
+        assert_eq!(word_at(value, 5), (3, 8));
+        assert_eq!(word_at(value, 3), (3, 8));
+        assert_eq!(word_at(value, 0), (0, 2));
+        assert_eq!(word_at(value, value.len()), (29, 33));
+    }
+
+    #[test]
+    fn describes_commands_but_not_references() {
+        let value = "on click toggle .is-open on #menu then wait 1s";
+        let (name, doc) = describe(value, 10).unwrap();
+        assert_eq!(name, "toggle");
+        assert!(doc.starts_with("```\n"));
+        assert!(describe(value, 20).is_none());
+        assert!(describe(value, 30).is_none());
+        assert_eq!(describe(value, 40).map(|(name, _)| name), Some("wait"));
+    }
+}
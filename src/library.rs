@@ -0,0 +1,308 @@
+//! Custom attributes described by definition files in the workspace, such as those of in-house htmx
+//! extensions
+//!
+//! Definition files are named `*.htmx.json` or `*.htmx.toml`:
+//!
+//! ```json
+//! {
+//!     "attributes": [
+//!         {
+//!             "name": "hx-track",
+//!             "description": "sends an analytics event when the element is used",
+//!             "value": { "type": "enum", "values": ["click", "view"] }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Attribute names are case insensitive like in HTML. When several files define the same attribute,
+//! the file whose uri sorts first wins
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use log::warn;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind, Uri,
+};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{textstore::FileData, values::completion, workspace::uri_to_path};
+
+/// The suffixes of definition files, with the format they are written in
+pub const DEFINITION_SUFFIXES: &[(&str, Format)] =
+    &[(".htmx.json", Format::Json), (".htmx.toml", Format::Toml)];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Json,
+    Toml,
+}
+
+#[derive(Debug, Deserialize)]
+struct DefinitionFile {
+    #[serde(default)]
+    attributes: Vec<Attribute>,
+}
+
+/// An attribute described by a definition file
+#[derive(Debug, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    /// Markdown shown on hover and completion
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub value: Grammar,
+}
+
+/// The values an attribute accepts
+#[derive(Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Grammar {
+    /// Anything
+    #[default]
+    Any,
+    /// `true` or `false`
+    Boolean,
+    /// One of the listed values
+    Enum { values: Vec<String> },
+    /// Values matching a regular expression as a whole
+    Pattern { pattern: Pattern },
+    /// An extended selector, checked against the page like `hx-target`
+    Selector,
+    /// A JSON object, like `hx-vals`
+    Json,
+}
+
+/// A regular expression matched against whole values
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pattern(Regex);
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Ok(Self(Regex::new(&format!("^(?:{pattern})$"))?))
+    }
+}
+
+impl Pattern {
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+
+    /// Returns the pattern as written in the definition file
+    pub fn as_str(&self) -> &str {
+        let pattern = self.0.as_str();
+        &pattern["^(?:".len()..pattern.len() - ")$".len()]
+    }
+}
+
+impl Grammar {
+    /// Returns the values to complete
+    pub fn values(&self) -> Vec<&str> {
+        match self {
+            Self::Boolean => vec!["true", "false"],
+            Self::Enum { values } => values.iter().map(String::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Returns the format of the file at `uri` if it is a definition file
+fn format_of(uri: &Uri) -> Option<Format> {
+    let path = uri_to_path(uri)?;
+    let name = path.file_name()?.to_str()?;
+    DEFINITION_SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, format)| *format)
+}
+
+/// The attributes described by every definition file in the workspace
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    files: HashMap<Uri, Vec<Attribute>>,
+}
+
+impl LibraryIndex {
+    /// Loads the definitions in the file at `uri`, whose content is `src`, replacing the ones it had
+    ///
+    /// Files that aren't definition files are ignored, and definition files that fail to parse are
+    /// dropped until they are fixed
+    pub fn update(&mut self, uri: &Uri, src: &str) {
+        let Some(format) = format_of(uri) else {
+            return;
+        };
+        let parsed = match format {
+            Format::Json => serde_json::from_str::<DefinitionFile>(src).map_err(|e| e.to_string()),
+            Format::Toml => basic_toml::from_str::<DefinitionFile>(src).map_err(|e| e.to_string()),
+        };
+        match parsed {
+            Ok(definitions) => {
+                for attribute in &definitions.attributes {
+                    let other = self.files.iter().find(|(other, attributes)| {
+                        *other != uri
+                            && attributes
+                                .iter()
+                                .any(|a| a.name.eq_ignore_ascii_case(&attribute.name))
+                    });
+                    if let Some((other, _)) = other {
+                        warn!(
+                            "{} is defined in both {} and {}",
+                            attribute.name,
+                            uri.as_str(),
+                            other.as_str()
+                        );
+                    }
+                }
+                self.files.insert(uri.clone(), definitions.attributes);
+            }
+            Err(err) => {
+                warn!("Invalid definition file {}: {err}", uri.as_str());
+                self.files.remove(uri);
+            }
+        }
+    }
+
+    /// Forgets the definitions in the file at `uri`, after it was deleted
+    pub fn remove(&mut self, uri: &Uri) {
+        self.files.remove(uri);
+    }
+
+    /// Iterates over the attributes of every definition file, leaving out those defined again by a
+    /// file whose uri sorts later
+    fn attributes(&self) -> impl Iterator<Item = &Attribute> {
+        self.files
+            .iter()
+            .sorted_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()))
+            .flat_map(|(_, attributes)| attributes)
+            .unique_by(|attribute| attribute.name.to_ascii_lowercase())
+    }
+
+    /// Returns the attribute named `name`, from any definition file
+    pub fn get(&self, name: &str) -> Option<&Attribute> {
+        self.attributes()
+            .find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Completes the attribute name `a` being typed
+    pub fn completions(&self, a: &str) -> Vec<CompletionItem> {
+        let a = a.to_ascii_lowercase();
+        self.attributes()
+            .filter(|attribute| attribute.name.to_ascii_lowercase().starts_with(&a))
+            .map(|attribute| CompletionItem {
+                label: attribute.name.clone(),
+                kind: Some(CompletionItemKind::FIELD),
+                documentation: Some(Documentation::MarkupContent(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: attribute.description.clone(),
+                })),
+                commit_characters: Some(vec![" ".into(), "=".into()]),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Completes the value of the attribute `key`, which is `value`, with the values its grammar
+    /// accepts
+    ///
+    /// Returns `None` for attributes that aren't defined or don't accept a fixed set of values
+    pub fn value_completions(
+        &self,
+        file: &FileData,
+        key: &str,
+        value: &str,
+    ) -> Option<Vec<CompletionItem>> {
+        let values = self.get(key)?.value.values();
+        let range = file.range_of(value);
+        (!values.is_empty()).then(|| {
+            values
+                .into_iter()
+                .map(|label| completion(label, CompletionItemKind::ENUM_MEMBER, range))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn index(files: &[(&str, &str)]) -> LibraryIndex {
+        let mut index = LibraryIndex::default();
+        for (uri, src) in files {
+            index.update(&Uri::from_str(uri).unwrap(), src);
+        }
+        index
+    }
+
+    #[test]
+    fn patterns_keep_their_source() {
+        let pattern = Pattern::try_from(r"\d+|auto".to_string()).unwrap();
+        assert_eq!(pattern.as_str(), r"\d+|auto");
+        assert!(pattern.is_match("auto"));
+        assert!(pattern.is_match("42"));
+        assert!(!pattern.is_match("42px"));
+    }
+
+    #[test]
+    fn loads_json_and_toml_definitions() {
+        let index = index(&[
+            (
+                "file:///a.htmx.json",
+                r#"{ "attributes": [{ "name": "hx-track", "value": { "type": "enum", "values": ["click"] } }] }"#,
+            ),
+            (
+                "file:///b.htmx.toml",
+                "[[attributes]]\nname = \"hx-flag\"\nvalue = { type = \"boolean\" }\n",
+            ),
+            (
+                "file:///c.json",
+                r#"{ "attributes": [{ "name": "hx-ignored" }] }"#,
+            ),
+        ]);
+        assert_eq!(index.get("hx-track").unwrap().value.values(), ["click"]);
+        assert_eq!(
+            index.get("hx-flag").unwrap().value.values(),
+            ["true", "false"]
+        );
+        assert!(index.get("hx-ignored").is_none());
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        let index = index(&[(
+            "file:///a.htmx.json",
+            r#"{ "attributes": [{ "name": "hx-Track" }] }"#,
+        )]);
+        assert!(index.get("HX-TRACK").is_some());
+        let labels: Vec<String> = index
+            .completions("HX-t")
+            .into_iter()
+            .map(|c| c.label)
+            .collect();
+        assert_eq!(labels, ["hx-Track"]);
+    }
+
+    #[test]
+    fn the_first_file_wins_for_duplicates() {
+        let index = index(&[
+            (
+                "file:///b.htmx.json",
+                r#"{ "attributes": [{ "name": "hx-track", "description": "b" }] }"#,
+            ),
+            (
+                "file:///a.htmx.json",
+                r#"{ "attributes": [{ "name": "HX-TRACK", "description": "a" }] }"#,
+            ),
+        ]);
+        assert_eq!(index.get("hx-track").unwrap().description, "a");
+        assert_eq!(index.completions("hx-").len(), 1);
+    }
+}
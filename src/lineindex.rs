@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use lsp_types::{Position, PositionEncodingKind, Range as LspRange};
+
+/// How the `character` of a `Position` is counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl Encoding {
+    /// Picks the first encoding offered by the client that we support, defaulting to UTF-16 as the
+    /// protocol requires
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Self {
+        offered
+            .into_iter()
+            .flatten()
+            .find_map(|kind| match kind.as_str() {
+                "utf-8" => Some(Self::Utf8),
+                "utf-16" => Some(Self::Utf16),
+                "utf-32" => Some(Self::Utf32),
+                _ => None,
+            })
+            .unwrap_or(Self::Utf16)
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+            Self::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// Returns how many units `c` takes up in this encoding
+    fn len(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Converts between byte offsets and LSP positions
+///
+/// Lines may end in `\n`, `\r\n` or `\r`, as the protocol allows. A document always has at least one
+/// line, and a trailing line break starts one more, empty line
+#[derive(Debug)]
+pub struct LineIndex {
+    /// Byte offset of the start of each line
+    starts: Vec<usize>,
+    encoding: Encoding,
+}
+
+impl LineIndex {
+    pub fn new(text: &str, encoding: Encoding) -> Self {
+        let bytes = text.as_bytes();
+        let mut starts = vec![0];
+        for (i, &b) in bytes.iter().enumerate() {
+            let is_break = b == b'\n' || (b == b'\r' && bytes.get(i + 1) != Some(&b'\n'));
+            if is_break {
+                starts.push(i + 1);
+            }
+        }
+        Self { starts, encoding }
+    }
+
+    /// Returns the byte range of `line` in `text`, excluding its line break
+    fn line(&self, text: &str, line: usize) -> Option<Range<usize>> {
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line + 1).map_or(text.len(), |&next| {
+            let content = &text.as_bytes()[start..next];
+            next - if content.ends_with(b"\r\n") { 2 } else { 1 }
+        });
+        Some(start..end)
+    }
+
+    /// Converts `pos` to a byte offset into `text`
+    ///
+    /// Characters past the end of a line are clamped to it, as the protocol requires. Returns `None` when
+    /// the line is past the end of the document
+    pub fn offset(&self, text: &str, pos: Position) -> Option<usize> {
+        let line = self.line(text, pos.line as usize)?;
+        let chr = pos.character as usize;
+        let mut units = 0;
+        for (i, c) in text[line.clone()].char_indices() {
+            if units >= chr {
+                return Some(line.start + i);
+            }
+            units += self.encoding.len(c);
+        }
+        Some(line.end)
+    }
+
+    /// Converts a byte offset into `text` to a position
+    ///
+    /// Offsets past the end of `text` are clamped to it
+    pub fn position(&self, text: &str, off: usize) -> Position {
+        let mut off = off.min(text.len());
+        while !text.is_char_boundary(off) {
+            off -= 1;
+        }
+        let line = self.starts.partition_point(|&s| s <= off) - 1;
+        let start = self.starts[line];
+        let chr: usize = text[start..off].chars().map(|c| self.encoding.len(c)).sum();
+        Position {
+            line: u32::try_from(line).unwrap_or(u32::MAX),
+            character: u32::try_from(chr).unwrap_or(u32::MAX),
+        }
+    }
+
+    pub fn range(&self, text: &str, range: Range<usize>) -> LspRange {
+        LspRange {
+            start: self.position(text, range.start),
+            end: self.position(text, range.end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    #[test]
+    fn negotiate_prefers_client_order() {
+        let offered = [PositionEncodingKind::UTF32, PositionEncodingKind::UTF8];
+        assert_eq!(Encoding::negotiate(Some(&offered)), Encoding::Utf32);
+        let offered = [PositionEncodingKind::new("utf-7")];
+        assert_eq!(Encoding::negotiate(Some(&offered)), Encoding::Utf16);
+        assert_eq!(Encoding::negotiate(None), Encoding::Utf16);
+    }
+
+    #[test]
+    fn counts_characters_in_each_encoding() {
+        let text = "é😀x\nb";
+        let x = text.find('x').unwrap();
+        for (encoding, chr) in [
+            (Encoding::Utf8, 6),
+            (Encoding::Utf16, 3),
+            (Encoding::Utf32, 2),
+        ] {
+            let index = LineIndex::new(text, encoding);
+            assert_eq!(index.position(text, x), pos(0, chr));
+            assert_eq!(index.offset(text, pos(0, chr)), Some(x));
+        }
+    }
+
+    #[test]
+    fn position_and_offset_round_trip() {
+        let text = "<div>\n  <p>ünï</p>\n</div>";
+        let index = LineIndex::new(text, Encoding::Utf16);
+        for (off, _) in text.char_indices() {
+            assert_eq!(index.offset(text, index.position(text, off)), Some(off));
+        }
+    }
+
+    #[test]
+    fn clamps_out_of_range_positions() {
+        let text = "ab\ncd";
+        let index = LineIndex::new(text, Encoding::Utf16);
+        assert_eq!(index.offset(text, pos(0, 10)), Some(2));
+        assert_eq!(index.offset(text, pos(2, 0)), None);
+        assert_eq!(index.position(text, 100), pos(1, 2));
+    }
+
+    #[test]
+    fn offsets_inside_a_character_round_down() {
+        let text = "é";
+        let index = LineIndex::new(text, Encoding::Utf16);
+        assert_eq!(index.position(text, 1), pos(0, 0));
+    }
+
+    #[test]
+    fn handles_every_line_break() {
+        let text = "a\r\nb\rc\nd";
+        let index = LineIndex::new(text, Encoding::Utf16);
+        assert_eq!(index.offset(text, pos(1, 0)), Some(3));
+        assert_eq!(index.offset(text, pos(2, 0)), Some(5));
+        assert_eq!(index.offset(text, pos(3, 0)), Some(7));
+        assert_eq!(index.position(text, 7), pos(3, 0));
+    }
+
+    #[test]
+    fn clamps_before_crlf() {
+        let text = "ab\r\ncd";
+        let index = LineIndex::new(text, Encoding::Utf16);
+        assert_eq!(index.offset(text, pos(0, 5)), Some(2));
+    }
+
+    #[test]
+    fn trailing_line_break_starts_an_empty_line() {
+        for text in ["<p></p>\n", "<p></p>\r\n"] {
+            let index = LineIndex::new(text, Encoding::Utf16);
+            assert_eq!(index.offset(text, pos(1, 0)), Some(text.len()));
+            assert_eq!(index.offset(text, pos(1, 4)), Some(text.len()));
+            assert_eq!(index.position(text, text.len()), pos(1, 0));
+            assert_eq!(index.offset(text, pos(2, 0)), None);
+        }
+        let index = LineIndex::new("", Encoding::Utf16);
+        assert_eq!(index.offset("", pos(0, 0)), Some(0));
+    }
+}
@@ -1,25 +1,55 @@
 #![warn(clippy::pedantic)]
+// `lsp_types::Uri` caches some of its parts in `Cell`s, which don't affect its hash
+#![allow(clippy::mutable_key_type)]
 
-use std::ops::Deref;
-
-use itertools::Itertools;
+use config::{Config, Library};
+use library::LibraryIndex;
+use lineindex::Encoding;
 use log::{error, info, warn};
-use lsp_server::{Connection, RequestId};
+use lsp_server::{Connection, ErrorCode};
 use lsp_server::{Message, Request};
-use lsp_types::request::{Completion};
 use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionOptions,
-    CompletionParams, CompletionResponse, DiagnosticOptions, DiagnosticServerCapabilities,
-    DidChangeTextDocumentParams, DidOpenTextDocumentParams, Documentation, Hover, HoverParams,
-    HoverProviderCapability, MarkupKind, Position, PositionEncodingKind, Range, ServerCapabilities,
+    CodeLens, CodeLensOptions, CodeLensParams, CompletionItem, CompletionItemKind,
+    CompletionOptions, CompletionParams, CompletionResponse, DiagnosticOptions,
+    DiagnosticServerCapabilities, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+    DidOpenTextDocumentParams, DocumentDiagnosticParams, DocumentDiagnosticReport,
+    DocumentDiagnosticReportResult, DocumentLink, DocumentLinkOptions, DocumentLinkParams,
+    Documentation, FileChangeType, FileSystemWatcher, FullDocumentDiagnosticReport, GlobPattern,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability,
+    InitializeParams, InitializeResult, MarkupKind, OneOf, Position, Registration,
+    RegistrationParams, RelatedFullDocumentDiagnosticReport, ServerCapabilities,
     TextDocumentSyncCapability, TextDocumentSyncKind, Uri, WorkDoneProgressOptions,
 };
-use textstore::TextStore;
+use routes::RouteIndex;
+use std::{
+    cell::OnceCell,
+    panic::{self, AssertUnwindSafe},
+};
+use textstore::{FileData, TextStore};
 use thiserror::Error;
-use tl::{Node, Parser};
+use workspace::TemplateGraph;
 
+mod alpine;
+mod config;
+mod datastar;
+mod diagnostics;
+mod embedded;
+mod extensions;
+mod fragments;
+// Generated by gen-htmx.sh
+#[allow(clippy::needless_raw_string_hashes)]
 mod htmx;
+mod hyperscript;
+mod library;
+mod lineindex;
+mod routes;
+mod selector;
+mod sse;
+mod template;
 mod textstore;
+mod values;
+mod workspace;
 
 #[derive(Debug, Error)]
 enum HandleMessageErr {
@@ -27,111 +57,192 @@ enum HandleMessageErr {
     FailedDeserialize(#[from] serde_json::Error),
     #[error("Unknown file uri: {0:?}")]
     BadUri(Uri),
+    #[error("Position out of bounds: {0:?}")]
+    BadPosition(Position),
+    #[error("Unknown method: {0}")]
+    UnknownMethod(String),
     #[error("Failed to send response")]
     SendError,
     #[error("Bad Message")]
     BadMsg,
+    #[error("Panicked while handling {0}")]
+    Panicked(String),
 }
 
-fn print_node(node: &Node, parser: &Parser, level: usize) {
-    let indent: String = std::iter::repeat_n("  ", level).join("");
-    match node {
-        Node::Tag(htmltag) => {
-            eprintln!("{}tag: {}", indent, htmltag.name().as_utf8_str());
-            for child in htmltag.children().top().iter() {
-                print_node(child.get(parser).unwrap(), parser, level + 1);
+impl HandleMessageErr {
+    /// The error code to respond with when a request fails with this error
+    fn code(&self) -> ErrorCode {
+        match self {
+            Self::FailedDeserialize(_) | Self::BadUri(_) | Self::BadPosition(_) | Self::BadMsg => {
+                ErrorCode::InvalidParams
             }
-        }
-        Node::Raw(content) => {
-            eprintln!(
-                "{}content: {:?} {}",
-                indent,
-                content,
-                content.as_bytes_borrowed().is_some()
-            );
-        }
-        Node::Comment(comment) => {
-            eprintln!(
-                "{}comment: {:?}, {}",
-                indent,
-                comment,
-                comment.as_bytes_borrowed().is_some()
-            );
+            Self::UnknownMethod(_) => ErrorCode::MethodNotFound,
+            Self::SendError | Self::Panicked(_) => ErrorCode::InternalError,
         }
     }
 }
 
-fn handle_hover(id: RequestId, params: HoverParams, state: &State) -> Result<(), HandleMessageErr> {
-    let uri = params.text_document_position_params.text_document.uri;
+/// Looks up the file at `uri` and converts `pos` to an offset into it
+fn file_position(
+    state: &State,
+    uri: Uri,
+    pos: Position,
+) -> Result<(&FileData, usize), HandleMessageErr> {
     let file = state
         .textstore
-        .0
+        .files
         .get(&uri)
-        .ok_or(HandleMessageErr::BadUri(uri))?;
-    let pos = params.text_document_position_params.position;
-    let off = file.line_to_offset(pos.line as usize, pos.character as usize);
+        .ok_or_else(|| HandleMessageErr::BadUri(uri))?;
+    let off = file
+        .position_to_offset(pos)
+        .ok_or(HandleMessageErr::BadPosition(pos))?;
+    Ok((file, off))
+}
+
+/// Describes an attribute added by an extension
+fn extension_doc(attribute: &extensions::Attribute) -> String {
+    format!(
+        "{}\n\nAdded by the `{}` extension",
+        attribute.description, attribute.extension
+    )
+}
+
+fn handle_hover(params: HoverParams, state: &State) -> Result<Option<Hover>, HandleMessageErr> {
+    let uri = params.text_document_position_params.text_document.uri;
+    let (file, off) = file_position(
+        state,
+        uri.clone(),
+        params.text_document_position_params.position,
+    )?;
     let Some(obj) = file.object_under_cursor(off) else {
         // Nothing to handle
-        return Ok(());
+        return Ok(None);
     };
+    warn!("Hovering over: {:?}", obj);
 
     match obj {
-        textstore::HTMLObject::Tag(_) => {
-            info!("Not implemented yet!");
+        textstore::HTMLObject::Tag(t) => {
+            info!("Not implemented yet! Tag: {t}");
+            Ok(None)
         }
         textstore::HTMLObject::Attr(a) => {
-            let Some(doc) = htmx::DESCRIPTIONS.get(a) else {
-                return Ok(());
-            };
-            state
-                .conn
-                .sender
-                .send(Message::Response(lsp_server::Response {
-                    id,
-                    result: Some(serde_json::to_value(Hover {
-                        contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: String::from(*doc),
-                        }),
-                        range: Some(Range {
-                            start: pos,
-                            end: Position {
-                                character: pos.character + a.len() as u32,
-                                ..pos
-                            },
-                        }),
-                    })?),
-                    error: None,
-                }))
-                .map_err(|_| HandleMessageErr::SendError)?;
+            let doc = htmx::DESCRIPTIONS
+                .get(a)
+                .map(|doc| String::from(*doc))
+                .or_else(|| alpine::describe(a).filter(|_| state.config.uses(Library::Alpine)))
+                .or_else(|| {
+                    let doc = hyperscript::DESCRIPTIONS.get(a)?;
+                    state
+                        .config
+                        .uses(Library::Hyperscript)
+                        .then(|| String::from(*doc))
+                })
+                .or_else(|| datastar::describe(a).filter(|_| state.config.uses(Library::Datastar)))
+                .or_else(|| {
+                    let description = &state.library.get(a)?.description;
+                    (!description.is_empty()).then(|| description.clone())
+                })
+                .or_else(|| {
+                    // Attributes of extensions are only described where the extension is enabled
+                    let attribute = extensions::lookup(a)?;
+                    let i = file.tag_of(a)?;
+                    extensions::enabled(&state.textstore, state.graph(), &uri, i)
+                        .contains(attribute.extension)
+                        .then(|| extension_doc(attribute))
+                });
+            Ok(doc.map(|doc| Hover {
+                contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: doc,
+                }),
+                range: Some(file.range_of(a)),
+            }))
+        }
+        textstore::HTMLObject::AttrValue { key, value } => {
+            Ok(values::hover(file, key, value, off, &state.config))
         }
-        textstore::HTMLObject::AttrValue(_) => info!("Not implemented yet!"),
     }
-    warn!("Hovering over: {:?}", obj);
-    Ok(())
+}
+
+/// Completes the attribute `a` with the attributes of the extensions enabled on its tag and of the other
+/// libraries used in the workspace
+fn library_completions(state: &State, uri: &Uri, file: &FileData, a: &str) -> Vec<CompletionItem> {
+    // Attributes of the extensions enabled on the tag being edited
+    let enabled = file
+        .tag_of(a)
+        .map(|i| extensions::enabled(&state.textstore, state.graph(), uri, i))
+        .unwrap_or_default();
+    // Attributes of definition files and of the other libraries used in the workspace
+    let uses = |library| state.config.uses(library);
+    state
+        .library
+        .completions(a)
+        .into_iter()
+        .chain(
+            alpine::completions(file, a)
+                .into_iter()
+                .filter(|_| uses(Library::Alpine)),
+        )
+        .chain(
+            datastar::completions(file, a)
+                .into_iter()
+                .filter(|_| uses(Library::Datastar)),
+        )
+        .chain(
+            hyperscript::ATTRIBUTES
+                .iter()
+                .filter(|_| uses(Library::Hyperscript))
+                .filter(|name| name.starts_with(a))
+                .map(|name| CompletionItem {
+                    label: String::from(*name),
+                    kind: Some(CompletionItemKind::FIELD),
+                    documentation: hyperscript::DESCRIPTIONS
+                        .get(*name)
+                        .map(|doc| Documentation::String(String::from(*doc))),
+                    ..Default::default()
+                }),
+        )
+        .chain(
+            extensions::ATTRIBUTES
+                .iter()
+                .filter(|attribute| attribute.name.starts_with(a))
+                .filter(|attribute| enabled.contains(attribute.extension))
+                .map(|attribute| CompletionItem {
+                    label: attribute.name.into(),
+                    kind: Some(CompletionItemKind::FIELD),
+                    detail: Some(format!("{} extension", attribute.extension)),
+                    documentation: Some(Documentation::String(extension_doc(attribute))),
+                    commit_characters: Some(vec![" ".into(), "=".into()]),
+                    ..Default::default()
+                }),
+        )
+        .collect()
 }
 
 fn handle_completion(
-    id: RequestId,
     params: CompletionParams,
     state: &State,
-) -> Result<(), HandleMessageErr> {
-    let uri = params.text_document_position.text_document.uri;
-    let file = state
-        .textstore
-        .0
-        .get(&uri)
-        .ok_or(HandleMessageErr::BadUri(uri))?;
+) -> Result<Option<CompletionResponse>, HandleMessageErr> {
     let pos = params.text_document_position.position;
-    let off = file.line_to_offset(pos.line as usize, pos.character as usize);
+    let uri = params.text_document_position.text_document.uri;
+    let (file, off) = file_position(state, uri.clone(), pos)?;
     info!("Completing: {} {:?}", off, pos);
-    let Some(obj) = file.object_under_cursor(off - 1) else {
+    // The cursor is just past the text being completed, or inside the quotes of a value, which may be
+    // empty
+    let obj = match file.object_under_cursor(off) {
+        Some(obj @ textstore::HTMLObject::AttrValue { .. }) => Some(obj),
+        _ => off
+            .checked_sub(1)
+            .and_then(|off| file.object_under_cursor(off)),
+    };
+    let Some(obj) = obj else {
         // Nothing to handle
-        return Ok(());
+        return Ok(None);
     };
     match obj {
-        textstore::HTMLObject::Tag(_) => {
-            info!("Not implemented yet");
+        textstore::HTMLObject::Tag(t) => {
+            info!("Not implemented yet: {t}");
+            Ok(None)
         }
         textstore::HTMLObject::Attr(a) => {
             // TODO: deprecate old attributes
@@ -161,73 +272,314 @@ fn handle_completion(
                     tags: None,
                 })
                 .collect();
+            let completions = completions
+                .into_iter()
+                .chain(library_completions(state, &uri, file, a))
+                .collect();
+            Ok(Some(CompletionResponse::Array(completions)))
+        }
+        textstore::HTMLObject::AttrValue { key, value } => {
+            if routes::VERB_ATTRIBUTES.contains(&key) {
+                return Ok(Some(CompletionResponse::Array(
+                    state.routes.completions(file, value),
+                )));
+            }
+            if let Some(completions) = values::completions(file, key, value, off, &state.config) {
+                return Ok(Some(CompletionResponse::Array(completions)));
+            }
+            if let Some(completions) = state.library.value_completions(file, key, value) {
+                return Ok(Some(CompletionResponse::Array(completions)));
+            }
+            info!("Not implemented yet: {key}={value}");
+            Ok(None)
+        }
+    }
+}
+
+/// Jumps from the url of a request to the backend handler serving it
+fn handle_definition(
+    params: GotoDefinitionParams,
+    state: &State,
+) -> Result<Option<GotoDefinitionResponse>, HandleMessageErr> {
+    let (file, off) = file_position(
+        state,
+        params.text_document_position_params.text_document.uri,
+        params.text_document_position_params.position,
+    )?;
+    let Some(textstore::HTMLObject::AttrValue { key, value }) = file.object_under_cursor(off)
+    else {
+        return Ok(None);
+    };
+    if !routes::VERB_ATTRIBUTES.contains(&key) {
+        return Ok(None);
+    }
+    let method = routes::method_of(key);
+    let locations = state.routes.handlers(&method, value);
+    Ok((!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations)))
+}
+
+/// Links the url of every request to the backend handler serving it
+fn handle_document_link(
+    params: DocumentLinkParams,
+    state: &State,
+) -> Result<Option<Vec<DocumentLink>>, HandleMessageErr> {
+    let uri = params.text_document.uri;
+    let file = state
+        .textstore
+        .files
+        .get(&uri)
+        .ok_or(HandleMessageErr::BadUri(uri))?;
+    let links = routes::requests(file)
+        .filter_map(|request| {
+            let location = state
+                .routes
+                .handlers(&request.method(), request.url)
+                .into_iter()
+                .next()?;
+            // Links can only point to files, going to the handler itself is left to goto definition
+            Some(DocumentLink {
+                range: file.range_of(request.url),
+                target: Some(location.uri),
+                tooltip: Some(format!(
+                    "{} {}",
+                    request.method(),
+                    file.source_of(request.url).trim()
+                )),
+                data: None,
+            })
+        })
+        .collect();
+    Ok(Some(links))
+}
+
+/// Shows which htmx elements request the routes registered in a backend source file
+fn handle_code_lens(params: CodeLensParams, state: &State) -> Vec<CodeLens> {
+    let uri = params.text_document.uri;
+    state.routes.code_lenses(&uri, &state.textstore)
+}
+
+fn handle_diagnostic(
+    params: DocumentDiagnosticParams,
+    state: &State,
+) -> Result<DocumentDiagnosticReportResult, HandleMessageErr> {
+    let uri = params.text_document.uri;
+    let items = diagnostics::diagnose(
+        &uri,
+        &state.textstore,
+        &state.config,
+        &state.routes,
+        &state.library,
+    )
+    .ok_or(HandleMessageErr::BadUri(uri))?;
+    Ok(DocumentDiagnosticReportResult::Report(
+        DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+            related_documents: None,
+            full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                result_id: None,
+                items,
+            },
+        }),
+    ))
+}
+
+fn handle_request(
+    state: &State,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, HandleMessageErr> {
+    Ok(match method {
+        "textDocument/hover" => {
+            serde_json::to_value(handle_hover(serde_json::from_value(params)?, state)?)?
+        }
+        "textDocument/completion" => {
+            serde_json::to_value(handle_completion(serde_json::from_value(params)?, state)?)?
+        }
+        "textDocument/definition" => {
+            serde_json::to_value(handle_definition(serde_json::from_value(params)?, state)?)?
+        }
+        "textDocument/documentLink" => serde_json::to_value(handle_document_link(
+            serde_json::from_value(params)?,
+            state,
+        )?)?,
+        "textDocument/codeLens" => {
+            serde_json::to_value(handle_code_lens(serde_json::from_value(params)?, state))?
+        }
+        "textDocument/diagnostic" => {
+            serde_json::to_value(handle_diagnostic(serde_json::from_value(params)?, state)?)?
+        }
+        _ => return Err(HandleMessageErr::UnknownMethod(method.to_string())),
+    })
+}
+
+fn handle_notification(
+    state: &mut State,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<(), HandleMessageErr> {
+    match method {
+        "textDocument/didOpen" => {
+            let params = serde_json::from_value::<DidOpenTextDocumentParams>(params)?;
             state
-                .conn
-                .sender
-                .send(Message::Response(lsp_server::Response {
-                    id,
-                    result: Some(serde_json::to_value(CompletionResponse::Array(
-                        completions,
-                    ))?),
-                    error: None,
-                }))
-                .map_err(|_| HandleMessageErr::SendError)?;
+                .routes
+                .update(&params.text_document.uri, &params.text_document.text);
+            state
+                .library
+                .update(&params.text_document.uri, &params.text_document.text);
+            state.textstore.insert(
+                params.text_document.uri,
+                &params.text_document.text,
+                Some(&params.text_document.language_id),
+            );
+            state.graph.take();
         }
-        textstore::HTMLObject::AttrValue(_) => {
-            info!("Not implemented yet");
+        "textDocument/didChange" => {
+            let params = serde_json::from_value::<DidChangeTextDocumentParams>(params)?;
+            let text = &params
+                .content_changes
+                .first()
+                .ok_or(HandleMessageErr::BadMsg)?
+                .text;
+            state.routes.update(&params.text_document.uri, text);
+            state.library.update(&params.text_document.uri, text);
+            state.textstore.insert(params.text_document.uri, text, None);
+            state.graph.take();
         }
+        "workspace/didChangeWatchedFiles" => {
+            // Definition files are reloaded from disk, since they are usually not open
+            let params = serde_json::from_value::<DidChangeWatchedFilesParams>(params)?;
+            for change in params.changes {
+                if change.typ == FileChangeType::DELETED {
+                    state.library.remove(&change.uri);
+                } else if let Some(content) = workspace::uri_to_path(&change.uri)
+                    .and_then(|path| std::fs::read_to_string(path).ok())
+                {
+                    state.library.update(&change.uri, &content);
+                }
+            }
+        }
+        "workspace/didChangeConfiguration" => {
+            let params = serde_json::from_value::<DidChangeConfigurationParams>(params)?;
+            if let Some(config) = Config::from_settings(params.settings)? {
+                state.config = config;
+            }
+        }
+        _ => {}
     }
     Ok(())
 }
 
+/// Handles a single message from the client
+///
+/// Every request gets a response, even when handling it fails or panics, so that a bad document can
+/// neither take down the server nor leave the client waiting forever
 fn handle_message(state: &mut State, msg: Message) -> Result<(), HandleMessageErr> {
     match msg {
-        lsp_server::Message::Request(Request { id, method, params }) => match method.as_str() {
-            "textDocument/hover" => {
-                handle_hover(id, serde_json::from_value(params)?, state)?;
-            }
-            "textDocument/completion" => {
-                handle_completion(id, serde_json::from_value(params)?, state)?;
-            }
-            _ => {}
-        },
-        lsp_server::Message::Notification(n) => match n.method.as_str() {
-            "textDocument/didOpen" => {
-                let params = serde_json::from_value::<DidOpenTextDocumentParams>(n.params)?;
-                state
-                    .textstore
-                    .insert(params.text_document.uri, &params.text_document.text);
-            }
-            "textDocument/didChange" => {
-                let params = serde_json::from_value::<DidChangeTextDocumentParams>(n.params)?;
-                state.textstore.insert(
-                    params.text_document.uri,
-                    &params
-                        .content_changes
-                        .first()
-                        .ok_or(HandleMessageErr::BadMsg)?
-                        .text,
-                );
-            }
-            _ => {}
-        },
-
+        lsp_server::Message::Request(Request { id, method, params }) => {
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| handle_request(state, &method, params)));
+            let response = match result {
+                Ok(Ok(result)) => lsp_server::Response::new_ok(id, result),
+                Ok(Err(err)) => {
+                    error!("Error while handling {method}: {err}");
+                    lsp_server::Response::new_err(id, err.code() as i32, err.to_string())
+                }
+                Err(_) => lsp_server::Response::new_err(
+                    id,
+                    ErrorCode::InternalError as i32,
+                    format!("Internal error while handling {method}"),
+                ),
+            };
+            state
+                .conn
+                .sender
+                .send(Message::Response(response))
+                .map_err(|_| HandleMessageErr::SendError)?;
+        }
+        lsp_server::Message::Notification(n) => {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                handle_notification(state, &n.method, n.params)
+            }))
+            .map_err(|_| HandleMessageErr::Panicked(n.method))??;
+        }
         lsp_server::Message::Response(_) => {}
     }
     Ok(())
 }
 
+/// Asks the client to notify the server of changes to definition files, if it can
+fn watch_definitions(conn: &Connection, params: &InitializeParams) {
+    let supported = params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+        .and_then(|watched| watched.dynamic_registration)
+        .unwrap_or(false);
+    if !supported {
+        return;
+    }
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: library::DEFINITION_SUFFIXES
+            .iter()
+            .map(|(suffix, _)| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/*{suffix}")),
+                kind: None,
+            })
+            .collect(),
+    };
+    let registration = RegistrationParams {
+        registrations: vec![Registration {
+            id: "htmx-definitions".into(),
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: serde_json::to_value(options).ok(),
+        }],
+    };
+    let request = Request::new(
+        "htmx-definitions".to_string().into(),
+        "client/registerCapability".into(),
+        registration,
+    );
+    if conn.sender.send(Message::Request(request)).is_err() {
+        error!("Failed to register file watchers");
+    }
+}
+
 struct State {
     pub conn: Connection,
     pub textstore: TextStore,
+    pub config: Config,
+    pub routes: RouteIndex,
+    pub library: LibraryIndex,
+    /// The template graph of `textstore`, built on first use after each change
+    graph: OnceCell<TemplateGraph>,
+}
+
+impl State {
+    fn graph(&self) -> &TemplateGraph {
+        self.graph
+            .get_or_init(|| TemplateGraph::new(&self.textstore))
+    }
 }
 
 fn main() {
     env_logger::init();
 
-    let (conn, _threads) = lsp_server::Connection::stdio();
+    let (conn, threads) = lsp_server::Connection::stdio();
+    let (id, params) = conn
+        .initialize_start()
+        .expect("Failed to receive initialize request");
+    let params: InitializeParams =
+        serde_json::from_value(params).expect("Failed to deserialize initialize params");
+    let encoding = Encoding::negotiate(
+        params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref()),
+    );
+    info!("Using position encoding {encoding:?}");
     let server_capabilities = ServerCapabilities {
-        position_encoding: Some(PositionEncodingKind::UTF8),
+        position_encoding: Some(encoding.kind()),
         text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
         hover_provider: Some(HoverProviderCapability::Simple(true)),
         completion_provider: Some(CompletionOptions {
@@ -239,9 +591,19 @@ fn main() {
             },
             completion_item: None,
         }),
+        definition_provider: Some(OneOf::Left(true)),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: Some(false),
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: Some(false),
+            },
+        }),
         diagnostic_provider: Some(DiagnosticServerCapabilities::Options(DiagnosticOptions {
             identifier: Some("htmx-lsp".into()),
-            inter_file_dependencies: false,
+            inter_file_dependencies: true,
             workspace_diagnostics: false,
             work_done_progress_options: WorkDoneProgressOptions {
                 work_done_progress: Some(false),
@@ -249,19 +611,53 @@ fn main() {
         })),
         ..Default::default()
     };
-    let _params = conn.initialize(
-        serde_json::to_value(server_capabilities).expect("Failed to serialize server capabilities"),
-    );
+    conn.initialize_finish(
+        id,
+        serde_json::to_value(InitializeResult {
+            capabilities: server_capabilities,
+            server_info: None,
+        })
+        .expect("Failed to serialize server capabilities"),
+    )
+    .expect("Failed to initialize");
+    let config = Config::from_settings(params.initialization_options.clone().unwrap_or_default())
+        .unwrap_or_else(|err| {
+            error!("Invalid initialization options: {err}");
+            None
+        })
+        .unwrap_or_default();
     let mut state = State {
         conn,
-        textstore: TextStore::new(),
+        textstore: TextStore::new(encoding),
+        config,
+        routes: RouteIndex::new(encoding),
+        library: LibraryIndex::default(),
+        graph: OnceCell::new(),
     };
+    workspace::Workspace::new(&params).index(
+        &mut state.textstore,
+        &mut state.routes,
+        &mut state.library,
+    );
+    watch_definitions(&state.conn, &params);
     info!("Initialized htmx language server");
 
-    loop {
-        let msg = state.conn.receiver.recv().expect("Failed to receive message from channel. Cannot continue") ;
+    while let Ok(msg) = state.conn.receiver.recv() {
+        if let Message::Request(req) = &msg {
+            match state.conn.handle_shutdown(req) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(err) => {
+                    error!("Error while shutting down: {err}");
+                    break;
+                }
+            }
+        }
         if let Err(err) = handle_message(&mut state, msg) {
             error!("Error while handling message: {:?}", err);
         }
     }
+    drop(state);
+    threads.join().expect("Failed to join IO threads");
+    info!("Shutting down htmx language server");
 }
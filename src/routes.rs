@@ -0,0 +1,909 @@
+//! Routes registered by the backend
+//!
+//! Each web framework has an extractor that finds route registrations in source files with regular
+//! expressions. They don't understand the host language, so unusual registrations are missed, but that
+//! is good enough to complete urls and catch typos in them
+//!
+//! Routers may be mounted under a prefix, like Flask blueprints or Django `include()`s. Which routes a
+//! prefix applies to isn't known, so routes match under any of them, and urls aren't checked when a
+//! prefix isn't a string literal
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    sync::LazyLock,
+};
+
+use itertools::Itertools;
+use lsp_types::{
+    CodeLens, Command, CompletionItem, CompletionItemKind, CompletionTextEdit, Location,
+    Range as LspRange, TextEdit, Uri,
+};
+use regex::{Match, Regex};
+
+use crate::{
+    lineindex::{Encoding, LineIndex},
+    textstore::{attribute, FileData, TextStore},
+    workspace::uri_to_path,
+};
+
+/// Attributes that issue a request to the url in their value
+pub const VERB_ATTRIBUTES: &[&str] = &["hx-get", "hx-post", "hx-put", "hx-patch", "hx-delete"];
+
+/// File extensions of the backend sources that are scanned for routes
+pub const SOURCE_EXTENSIONS: &[&str] = &["py", "rs", "go", "js", "mjs", "cjs", "ts"];
+
+/// An element that issues a request when triggered
+#[derive(Debug)]
+pub struct Request<'a> {
+    /// The index of the element in the `dom` of the file
+    pub node: usize,
+    /// The attribute declaring the request, e.g. `hx-get`
+    pub verb: &'static str,
+    pub url: &'a str,
+}
+
+impl Request<'_> {
+    /// The HTTP method of the request, e.g. `GET`
+    pub fn method(&self) -> String {
+        method_of(self.verb)
+    }
+}
+
+/// Returns the HTTP method of requests issued by a verb attribute, e.g. `GET` for `hx-get`
+pub fn method_of(verb: &str) -> String {
+    verb.trim_start_matches("hx-").to_uppercase()
+}
+
+/// Iterates over the requests issued by elements in `file`
+pub fn requests(file: &FileData) -> impl Iterator<Item = Request<'_>> {
+    file.tags().flat_map(|(node, tag)| {
+        VERB_ATTRIBUTES.iter().filter_map(move |&verb| {
+            Some(Request {
+                node,
+                verb,
+                url: attribute(tag, verb)?,
+            })
+        })
+    })
+}
+
+/// Strips the scheme, host, query string and fragment off `url`
+///
+/// Trailing whitespace is kept, since it may be a template expression that was masked out
+pub fn url_path(url: &str) -> &str {
+    let url = url.trim_start();
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => url,
+    }
+}
+
+/// Returns whether a route segment is a parameter, e.g. `<id>`, `{id}` or `:id`
+pub fn is_param(segment: &str) -> bool {
+    (segment.starts_with('<') && segment.ends_with('>'))
+        || (segment.starts_with('{') && segment.ends_with('}'))
+        || segment.starts_with(':')
+}
+
+/// Returns whether a route segment matches the rest of the path, e.g. `<path:rest>`, `*rest` or
+/// `{rest...}`
+pub fn is_catch_all(segment: &str) -> bool {
+    segment.starts_with('*')
+        || segment.starts_with("<path:")
+        || segment.starts_with("{*")
+        || segment.ends_with("...}")
+}
+
+/// Returns whether `url` may be a request to `route`
+///
+/// Route parameters match any segment, and so do segments of `url` produced by templates, which have
+/// been masked out to spaces
+pub fn url_matches(route: &str, url: &str) -> bool {
+    let mut route = route.trim_matches('/').split('/');
+    let mut url = url_path(url).trim_matches('/').split('/');
+    loop {
+        match (route.next(), url.next()) {
+            (None, None) => return true,
+            (Some(r), _) if is_catch_all(r) => return true,
+            (Some(r), Some(u)) if r == u || is_param(r) || u.contains(' ') => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Returns how specific `route` is, which is the number of literal segments in it, then which of its
+/// segments are literal from left to right
+///
+/// When several routes match a url, the most specific one wins, so that `/contacts/count` is preferred
+/// over `/contacts/<id>`, and `/contacts/<id>` over `/<kind>/1`
+pub fn specificity(route: &str) -> (usize, Vec<bool>) {
+    let literals: Vec<bool> = route
+        .trim_matches('/')
+        .split('/')
+        .map(|s| !is_param(s) && !is_catch_all(s))
+        .collect();
+    (
+        literals.iter().filter(|&&literal| literal).count(),
+        literals,
+    )
+}
+
+/// A route registered by the backend
+#[derive(Debug)]
+pub struct Route {
+    /// The path pattern, e.g. `/contacts/<int:id>`, which always starts with `/`
+    pub path: String,
+    /// The HTTP methods the route accepts, in uppercase, or nothing when it accepts any
+    pub methods: Vec<String>,
+    /// The byte range of the path in the source file
+    pub span: Range<usize>,
+    /// The name of the function handling the route, when it isn't defined inline
+    pub handler: Option<String>,
+}
+
+impl Route {
+    fn new(path: Match, methods: Vec<String>, handler: Option<&str>) -> Self {
+        Self {
+            path: format!("/{}", path.as_str().trim_start_matches('/')),
+            methods,
+            span: path.range(),
+            // Handlers are often referred to through their module, e.g. `views.archive`
+            handler: handler
+                .map(|h| h.trim_end_matches(".as_view"))
+                .and_then(|h| h.rsplit(['.', ':']).next())
+                .filter(|h| !h.is_empty())
+                .map(String::from),
+        }
+    }
+
+    /// Returns whether the route accepts requests with `method`
+    pub fn accepts(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m == method)
+    }
+}
+
+/// Finds the routes registered with a particular framework
+trait Extractor: Sync {
+    fn routes(&self, src: &str) -> Vec<Route>;
+
+    /// Finds the prefixes routers are mounted under, whose routes are registered relative to them
+    ///
+    /// Prefixes that aren't string literals are `None`
+    fn prefixes(&self, _src: &str) -> Vec<Option<String>> {
+        Vec::new()
+    }
+}
+
+/// Returns the prefixes written in the first capture group of `pattern`, or `None` for matches where
+/// that group didn't take part, which are prefixes that aren't string literals
+fn captured_prefixes(pattern: &Regex, src: &str) -> Vec<Option<String>> {
+    pattern
+        .captures_iter(src)
+        .map(|c| {
+            c.get(1)
+                .map(|prefix| format!("/{}", prefix.as_str().trim_start_matches('/')))
+        })
+        .collect()
+}
+
+/// Returns the quoted strings in `s`, in uppercase
+fn quoted_methods(s: &str) -> Vec<String> {
+    static QUOTED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"["'](\w+)["']"#).unwrap());
+    QUOTED
+        .captures_iter(s)
+        .map(|c| c[1].to_uppercase())
+        .collect()
+}
+
+/// Flask and `FastAPI` decorators, e.g. `@app.route("/contacts", methods=["GET", "POST"])` or
+/// `@router.get("/contacts")`
+struct Flask;
+
+impl Extractor for Flask {
+    fn routes(&self, src: &str) -> Vec<Route> {
+        static DECORATOR: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(
+                r#"@[\w.]+\.(route|get|post|put|patch|delete)\(\s*(?:rule\s*=\s*|path\s*=\s*)?[rbu]?["']([^"'\n]*)["']([^\n]*)"#,
+            )
+            .unwrap()
+        });
+        static METHODS: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"methods\s*=\s*[\[(\{]([^\])}]*)").unwrap());
+        static DEF: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"(?:async\s+)?def\s+(\w+)").unwrap());
+        DECORATOR
+            .captures_iter(src)
+            .map(|c| {
+                let methods = match &c[1] {
+                    "route" => METHODS
+                        .captures(&c[3])
+                        .map_or_else(|| vec!["GET".into()], |m| quoted_methods(&m[1])),
+                    verb => vec![verb.to_uppercase()],
+                };
+                // The decorated function, below any other decorators
+                let handler = DEF
+                    .captures(&src[c.get(0).unwrap().end()..])
+                    .map(|d| d.get(1).unwrap().as_str());
+                Route::new(c.get(2).unwrap(), methods, handler)
+            })
+            .collect()
+    }
+
+    /// Blueprints and `FastAPI` routers, e.g. `Blueprint("contacts", __name__, url_prefix="/contacts")`
+    /// or `app.include_router(router, prefix="/api")`
+    fn prefixes(&self, src: &str) -> Vec<Option<String>> {
+        static PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(
+                r#"\b(?:url_prefix|(?:APIRouter|include_router)\([^)]*?\bprefix)\s*=\s*(?:[rbu]?["']([^"'\n]*)["']|\w)"#,
+            )
+            .unwrap()
+        });
+        captured_prefixes(&PREFIX, src)
+    }
+}
+
+/// Django url patterns, e.g. `path("contacts/<int:id>/", views.contact)`
+struct Django;
+
+impl Extractor for Django {
+    fn routes(&self, src: &str) -> Vec<Route> {
+        static PATH: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r#"(?:^|[^\w.])path\(\s*r?["']([^"'\n]*)["'](?:\s*,\s*([\w.]+))?"#).unwrap()
+        });
+        PATH.captures_iter(src)
+            .filter(|c| c.get(2).is_none_or(|h| h.as_str() != "include"))
+            .map(|c| {
+                let handler = c.get(2).map(|h| h.as_str());
+                Route::new(c.get(1).unwrap(), Vec::new(), handler)
+            })
+            .collect()
+    }
+
+    /// Included url patterns, e.g. `path("contacts/", include("contacts.urls"))`
+    fn prefixes(&self, src: &str) -> Vec<Option<String>> {
+        static INCLUDE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r#"(?:^|[^\w.])path\(\s*r?["']([^"'\n]*)["']\s*,\s*include\("#).unwrap()
+        });
+        captured_prefixes(&INCLUDE, src)
+    }
+}
+
+/// Axum routers, e.g. `.route("/contacts", get(list).post(create))`, which register a route per method
+struct Axum;
+
+impl Extractor for Axum {
+    fn routes(&self, src: &str) -> Vec<Route> {
+        static ROUTE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#"\.route\(\s*"([^"\n]*)"\s*,"#).unwrap());
+        static METHOD: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"\b(get|post|put|patch|delete|any)(?:_service)?\s*\(\s*([\w:]+)?").unwrap()
+        });
+        ROUTE
+            .captures_iter(src)
+            .flat_map(|c| {
+                let path = c.get(1).unwrap();
+                // The method router is everything up to the parenthesis closing `.route(`
+                let rest = &src[c.get(0).unwrap().end()..];
+                let mut depth = 0;
+                let end = rest
+                    .char_indices()
+                    .find(|&(_, ch)| {
+                        match ch {
+                            '(' => depth += 1,
+                            ')' if depth == 0 => return true,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                        false
+                    })
+                    .map_or(rest.len(), |(i, _)| i);
+                let mut routes: Vec<Route> = METHOD
+                    .captures_iter(&rest[..end])
+                    .map(|m| {
+                        let methods = match &m[1] {
+                            "any" => Vec::new(),
+                            method => vec![method.to_uppercase()],
+                        };
+                        Route::new(path, methods, m.get(2).map(|h| h.as_str()))
+                    })
+                    .collect();
+                if routes.is_empty() {
+                    routes.push(Route::new(path, Vec::new(), None));
+                }
+                routes
+            })
+            .collect()
+    }
+
+    /// Nested routers, e.g. `.nest("/api", api_routes())`
+    fn prefixes(&self, src: &str) -> Vec<Option<String>> {
+        static NEST: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#"\.nest(?:_service)?\(\s*(?:"([^"\n]*)"|\w)"#).unwrap());
+        captured_prefixes(&NEST, src)
+    }
+}
+
+/// Express apps and routers, e.g. `app.get("/contacts", ...)`
+struct Express;
+
+impl Extractor for Express {
+    fn routes(&self, src: &str) -> Vec<Route> {
+        static ROUTE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(
+                r#"\b\w+\.(get|post|put|patch|delete|all)\(\s*["'`](/[^"'`\n]*)["'`](?:\s*,\s*(\w+))?"#,
+            )
+            .unwrap()
+        });
+        ROUTE
+            .captures_iter(src)
+            .map(|c| {
+                let methods = match &c[1] {
+                    "all" => Vec::new(),
+                    verb => vec![verb.to_uppercase()],
+                };
+                // Inline handlers start with `(`, `function` or `async`
+                let handler = c
+                    .get(3)
+                    .map(|h| h.as_str())
+                    .filter(|h| !matches!(*h, "function" | "async"));
+                Route::new(c.get(2).unwrap(), methods, handler)
+            })
+            .collect()
+    }
+
+    /// Mounted routers, e.g. `app.use("/contacts", contacts)`
+    fn prefixes(&self, src: &str) -> Vec<Option<String>> {
+        static USE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r#"\b\w+\.use\(\s*["'`](/[^"'`\n]*)["'`]\s*,"#).unwrap());
+        captured_prefixes(&USE, src)
+    }
+}
+
+/// Go's `net/http` muxes, e.g. `mux.HandleFunc("GET /contacts/{id}", show)`, and routers with a method
+/// per verb like chi, echo and gin, e.g. `r.Get("/contacts", list)`
+struct Go;
+
+impl Extractor for Go {
+    fn routes(&self, src: &str) -> Vec<Route> {
+        static HANDLE: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(
+                r#"\bHandle(?:Func)?\(\s*"(?:([A-Z]+)\s+)?(?:[\w.-]*)(/[^"\n]*)"(?:\s*,\s*([\w.]+))?"#,
+            )
+            .unwrap()
+        });
+        static METHOD: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(
+                r#"\.(Get|Post|Put|Patch|Delete|Any|GET|POST|PUT|PATCH|DELETE)\(\s*"(/[^"\n]*)"(?:\s*,\s*([\w.]+))?"#,
+            )
+            .unwrap()
+        });
+        let handles = HANDLE.captures_iter(src).map(|c| {
+            let methods = c.get(1).map(|m| vec![m.as_str().to_string()]);
+            let handler = c.get(3).map(|h| h.as_str());
+            Route::new(c.get(2).unwrap(), methods.unwrap_or_default(), handler)
+        });
+        let methods = METHOD.captures_iter(src).map(|c| {
+            let methods = match &c[1] {
+                "Any" => Vec::new(),
+                method => vec![method.to_uppercase()],
+            };
+            Route::new(c.get(2).unwrap(), methods, c.get(3).map(|h| h.as_str()))
+        });
+        handles.chain(methods).collect()
+    }
+
+    /// Subrouters, e.g. `r.Route("/contacts", ...)` and `r.Mount("/api", ...)` with chi,
+    /// `r.PathPrefix("/api").Subrouter()` with gorilla, or `e.Group("/api")` with echo and gin
+    fn prefixes(&self, src: &str) -> Vec<Option<String>> {
+        static PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r#"\.(?:Route|Mount|PathPrefix|Group)\(\s*(?:"(/[^"\n]*)"|\w)"#).unwrap()
+        });
+        captured_prefixes(&PREFIX, src)
+    }
+}
+
+/// Picks the frameworks that may register routes in a file with the given extension
+fn extractors_for(extension: &str) -> &'static [&'static dyn Extractor] {
+    match extension {
+        "py" => &[&Flask, &Django],
+        "rs" => &[&Axum],
+        "go" => &[&Go],
+        "js" | "mjs" | "cjs" | "ts" => &[&Express],
+        _ => &[],
+    }
+}
+
+/// Finds the names and byte ranges of the functions defined in a source file with the given extension
+fn definitions(extension: &str, src: &str) -> Vec<(String, Range<usize>)> {
+    static PYTHON: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?m)^[ \t]*(?:async[ \t]+)?(?:def|class)[ \t]+(\w+)").unwrap()
+    });
+    static RUST: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\bfn\s+(\w+)").unwrap());
+    static GO: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\bfunc\s+(?:\([^)]*\)\s*)?(\w+)").unwrap());
+    static JAVASCRIPT: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"\bfunction\s*\*?\s*(\w+)|\b(?:const|let|var)\s+(\w+)\s*=").unwrap()
+    });
+    let pattern: &Regex = match extension {
+        "py" => &PYTHON,
+        "rs" => &RUST,
+        "go" => &GO,
+        "js" | "mjs" | "cjs" | "ts" => &JAVASCRIPT,
+        _ => return Vec::new(),
+    };
+    pattern
+        .captures_iter(src)
+        .filter_map(|c| {
+            let name = c.get(1).or_else(|| c.get(2))?;
+            Some((name.as_str().to_string(), name.range()))
+        })
+        .collect()
+}
+
+/// A backend source file
+#[derive(Debug)]
+struct SourceFile {
+    text: Box<str>,
+    lines: LineIndex,
+    extension: String,
+    routes: Vec<Route>,
+    /// The prefixes routers are mounted under, which aren't known when they aren't string literals
+    prefixes: Vec<Option<String>>,
+    /// Functions defined in the file, which may be route handlers
+    definitions: Vec<(String, Range<usize>)>,
+}
+
+impl SourceFile {
+    fn location(&self, uri: &Uri, span: Range<usize>) -> Location {
+        Location {
+            uri: uri.clone(),
+            range: self.lines.range(&self.text, span),
+        }
+    }
+}
+
+/// The routes found in every backend source file
+#[derive(Debug)]
+pub struct RouteIndex {
+    files: HashMap<Uri, SourceFile>,
+    /// The position encoding negotiated with the client
+    encoding: Encoding,
+}
+
+impl RouteIndex {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            files: HashMap::new(),
+            encoding,
+        }
+    }
+
+    /// Extracts the routes and functions from the source file at `uri`, replacing any found in it before
+    pub fn update(&mut self, uri: &Uri, src: &str) {
+        let extension = uri_to_path(uri)
+            .and_then(|path| Some(path.extension()?.to_str()?.to_string()))
+            .unwrap_or_default();
+        if !SOURCE_EXTENSIONS.contains(&extension.as_str()) {
+            return;
+        }
+        let extractors = extractors_for(&extension);
+        let routes = extractors
+            .iter()
+            .flat_map(|extractor| extractor.routes(src))
+            .collect();
+        let prefixes = extractors
+            .iter()
+            .flat_map(|extractor| extractor.prefixes(src))
+            .collect();
+        let file = SourceFile {
+            text: src.into(),
+            lines: LineIndex::new(src, self.encoding),
+            definitions: definitions(&extension, src),
+            extension,
+            routes,
+            prefixes,
+        };
+        self.files.insert(uri.clone(), file);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes().next().is_none()
+    }
+
+    /// Iterates over every known route, along with the file registering it
+    pub fn routes(&self) -> impl Iterator<Item = (&Uri, &Route)> {
+        self.files
+            .iter()
+            .flat_map(|(uri, file)| file.routes.iter().map(move |route| (uri, route)))
+    }
+
+    /// Returns whether some routers are mounted under prefixes that aren't known, so that urls may
+    /// request routes that can't be matched
+    pub fn has_unknown_prefixes(&self) -> bool {
+        self.files
+            .values()
+            .flat_map(|file| &file.prefixes)
+            .any(Option::is_none)
+    }
+
+    /// Iterates over the routes that `url` may be a request to
+    ///
+    /// Which router a route is registered on isn't known, so routes also match under any of the known
+    /// prefixes
+    pub fn matching<'a>(&'a self, url: &'a str) -> impl Iterator<Item = (&'a Uri, &'a Route)> {
+        let prefixes: Vec<&str> = self
+            .files
+            .values()
+            .flat_map(|file| &file.prefixes)
+            .flatten()
+            .map(String::as_str)
+            .unique()
+            .collect();
+        self.routes().filter(move |(_, route)| {
+            url_matches(&route.path, url)
+                || prefixes.iter().any(|prefix| {
+                    let path = format!("{}{}", prefix.trim_end_matches('/'), route.path);
+                    url_matches(&path, url)
+                })
+        })
+    }
+
+    /// Returns where the handler of `route`, registered in the file at `uri`, is defined
+    ///
+    /// Handlers are looked up by name, first in the file registering the route, then in every other file
+    /// in the same language. When the handler is inline or can't be found, this is where the route is
+    /// registered instead
+    pub fn handler(&self, uri: &Uri, route: &Route) -> Option<Location> {
+        let file = self.files.get(uri)?;
+        let Some(name) = &route.handler else {
+            return Some(file.location(uri, route.span.clone()));
+        };
+        let find = |file: &SourceFile| {
+            file.definitions
+                .iter()
+                .find(|(def, _)| def == name)
+                .map(|(_, span)| span.clone())
+        };
+        let definition = find(file).map(|span| file.location(uri, span)).or_else(|| {
+            self.files
+                .iter()
+                .filter(|(_, other)| other.extension == file.extension)
+                .find_map(|(uri, other)| Some(other.location(uri, find(other)?)))
+        });
+        Some(definition.unwrap_or_else(|| file.location(uri, route.span.clone())))
+    }
+
+    /// Returns the routes a request with `method` to `url` may be handled by
+    ///
+    /// Routes that accept `method` are preferred, and of those, the most specific ones
+    fn resolve<'a>(&'a self, method: &str, url: &'a str) -> Vec<(&'a Uri, &'a Route)> {
+        let matching: Vec<_> = self.matching(url).collect();
+        let accepting: Vec<_> = matching
+            .iter()
+            .filter(|(_, route)| route.accepts(method))
+            .copied()
+            .collect();
+        let candidates = if accepting.is_empty() {
+            matching
+        } else {
+            accepting
+        };
+        let best = candidates
+            .iter()
+            .map(|(_, route)| specificity(&route.path))
+            .max();
+        candidates
+            .into_iter()
+            .filter(|(_, route)| Some(specificity(&route.path)) == best)
+            .collect()
+    }
+
+    /// Returns the handlers of the routes a request with `method` to `url` may be handled by
+    pub fn handlers(&self, method: &str, url: &str) -> Vec<Location> {
+        let mut locations: Vec<Location> = self
+            .resolve(method, url)
+            .into_iter()
+            .filter_map(|(uri, route)| self.handler(uri, route))
+            .collect();
+        locations.dedup();
+        locations
+    }
+
+    /// Shows which elements in `textstore` request each route registered in the file at `uri`
+    ///
+    /// The lens is put on the handler when it is defined in the same file, and on the route otherwise
+    pub fn code_lenses(&self, uri: &Uri, textstore: &TextStore) -> Vec<CodeLens> {
+        let Some(source) = self.files.get(uri) else {
+            return Vec::new();
+        };
+        // The elements requesting each route, by the index of the route in `source`
+        let mut requesters: Vec<Vec<Location>> = vec![Vec::new(); source.routes.len()];
+        for (template, file) in &textstore.files {
+            for request in requests(file) {
+                for (route_uri, route) in self.resolve(&request.method(), request.url) {
+                    if route_uri != uri {
+                        continue;
+                    }
+                    if let Some(i) = source.routes.iter().position(|r| std::ptr::eq(r, route)) {
+                        requesters[i].push(Location {
+                            uri: template.clone(),
+                            range: file.range_of(request.url),
+                        });
+                    }
+                }
+            }
+        }
+        let mut lenses: Vec<(LspRange, Vec<Location>)> = Vec::new();
+        for (route, requesters) in source.routes.iter().zip(requesters) {
+            let range = self
+                .handler(uri, route)
+                .filter(|location| location.uri == *uri)
+                .map_or_else(
+                    || source.lines.range(&source.text, route.span.clone()),
+                    |location| location.range,
+                );
+            match lenses.iter_mut().find(|(r, _)| *r == range) {
+                Some((_, existing)) => existing.extend(requesters),
+                None => lenses.push((range, requesters)),
+            }
+        }
+        lenses
+            .into_iter()
+            .map(|(range, locations)| {
+                let names = locations
+                    .iter()
+                    .filter_map(|l| Some(uri_to_path(&l.uri)?.file_name()?.to_str()?.to_string()))
+                    .unique()
+                    .join(", ");
+                let title = match locations.len() {
+                    0 => "not requested by any htmx element".to_string(),
+                    1 => format!("requested by 1 htmx element in {names}"),
+                    n => format!("requested by {n} htmx elements in {names}"),
+                };
+                CodeLens {
+                    range,
+                    // Without a command to run, clients show the title as a label
+                    command: Some(Command {
+                        title,
+                        command: String::new(),
+                        arguments: None,
+                    }),
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Completes the url in `value`, which must be a slice of `file.data`, with every known route
+    pub fn completions(&self, file: &FileData, value: &str) -> Vec<CompletionItem> {
+        // The same path is often registered once per method
+        let mut paths: BTreeMap<&str, (Vec<&str>, Vec<&Uri>)> = BTreeMap::new();
+        for (uri, route) in self.routes() {
+            let (methods, uris) = paths.entry(&route.path).or_default();
+            if route.methods.is_empty() {
+                methods.push("ANY");
+            }
+            methods.extend(route.methods.iter().map(String::as_str));
+            uris.push(uri);
+        }
+        let range = file.range_of(value);
+        paths
+            .into_iter()
+            .map(|(path, (mut methods, uris))| {
+                methods.sort_unstable();
+                methods.dedup();
+                let files = uris
+                    .iter()
+                    .filter_map(|uri| Some(uri_to_path(uri)?.file_name()?.to_str()?.to_string()))
+                    .collect::<Vec<_>>();
+                CompletionItem {
+                    label: path.to_string(),
+                    kind: Some(CompletionItemKind::REFERENCE),
+                    detail: Some(format!("{} {}", methods.join(", "), files.join(", "))),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range,
+                        new_text: path.to_string(),
+                    })),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn url_path_strips_origin_query_and_fragment() {
+        assert_eq!(url_path("/contacts?page=2"), "/contacts");
+        assert_eq!(url_path("https://example.com/contacts#top"), "/contacts");
+        assert_eq!(url_path("https://example.com"), "/");
+        assert_eq!(url_path(" /contacts/  "), "/contacts/  ");
+    }
+
+    #[test]
+    fn url_matches_literals_and_parameters() {
+        assert!(url_matches("/contacts", "/contacts/"));
+        assert!(url_matches("/contacts/<int:id>", "/contacts/3"));
+        assert!(url_matches("/contacts/{id}/edit", "/contacts/3/edit"));
+        assert!(url_matches("/contacts/:id", "/contacts/3?x=1"));
+        assert!(!url_matches("/contacts/<id>", "/contacts"));
+        assert!(!url_matches("/contacts", "/contacts/3"));
+        assert!(!url_matches("/contacts/count", "/contacts/3"));
+    }
+
+    #[test]
+    fn url_matches_catch_alls_and_templated_segments() {
+        assert!(url_matches("/static/<path:rest>", "/static/css/app.css"));
+        assert!(url_matches("/files/*rest", "/files/a/b"));
+        assert!(url_matches("/contacts/count", "/contacts/        "));
+    }
+
+    #[test]
+    fn specificity_prefers_literals_then_earlier_literals() {
+        assert!(specificity("/contacts/count") > specificity("/contacts/<id>"));
+        assert!(specificity("/contacts/<id>") > specificity("/<kind>/1"));
+    }
+
+    fn paths(routes: &[Route]) -> Vec<(&str, Vec<&str>, Option<&str>)> {
+        routes
+            .iter()
+            .map(|route| {
+                let methods = route.methods.iter().map(String::as_str).collect();
+                (route.path.as_str(), methods, route.handler.as_deref())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn extracts_flask_routes() {
+        let src = r#"
+@app.route("/contacts", methods=["GET", "POST"])
+@login_required
+def contacts():
+    pass
+
+@bp.delete('/contacts/<int:id>')
+async def delete(id):
+    pass
+"#;
+        assert_eq!(
+            paths(&Flask.routes(src)),
+            [
+                ("/contacts", vec!["GET", "POST"], Some("contacts")),
+                ("/contacts/<int:id>", vec!["DELETE"], Some("delete")),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_django_routes_but_not_includes() {
+        let src = r#"urlpatterns = [
+    path("contacts/<int:id>/", views.contact),
+    path("api/", include("api.urls")),
+]"#;
+        assert_eq!(
+            paths(&Django.routes(src)),
+            [("/contacts/<int:id>/", vec![], Some("contact"))]
+        );
+        assert_eq!(Django.prefixes(src), [Some("/api/".to_string())]);
+    }
+
+    #[test]
+    fn extracts_axum_routes_per_method() {
+        let src = r#"Router::new().route("/contacts", get(handlers::list).post(create)).route("/health", any_service(svc))"#;
+        assert_eq!(
+            paths(&Axum.routes(src)),
+            [
+                ("/contacts", vec!["GET"], Some("list")),
+                ("/contacts", vec!["POST"], Some("create")),
+                ("/health", vec![], Some("svc")),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_express_routes() {
+        let src = "app.get('/contacts', list);\nrouter.all(`/any`, (req, res) => {});";
+        assert_eq!(
+            paths(&Express.routes(src)),
+            [
+                ("/contacts", vec!["GET"], Some("list")),
+                ("/any", vec![], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_go_routes() {
+        let src = r#"mux.HandleFunc("GET /contacts/{id}", show)
+r.Post("/contacts", handlers.Create)
+e.Any("/any", anything)"#;
+        assert_eq!(
+            paths(&Go.routes(src)),
+            [
+                ("/contacts/{id}", vec!["GET"], Some("show")),
+                ("/contacts", vec!["POST"], Some("Create")),
+                ("/any", vec![], Some("anything")),
+            ]
+        );
+    }
+
+    #[test]
+    fn extracts_prefixes() {
+        let known = |prefix: &str| Some(prefix.to_string());
+        assert_eq!(
+            Flask.prefixes(
+                "bp = Blueprint('c', __name__, url_prefix='/contacts')\napp.include_router(r, prefix=PREFIX)\nmkstemp(prefix='tmp')"
+            ),
+            [known("/contacts"), None]
+        );
+        assert_eq!(
+            Axum.prefixes(r#".nest("/api", api()).nest(PREFIX, other())"#),
+            [known("/api"), None]
+        );
+        assert_eq!(
+            Express.prefixes("app.use('/contacts', contacts); app.use(cors());"),
+            [known("/contacts")]
+        );
+        assert_eq!(
+            Go.prefixes(r#"r.Route("/contacts", func(r chi.Router) {}); s := r.PathPrefix("/api").Subrouter()"#),
+            [known("/contacts"), known("/api")]
+        );
+    }
+
+    #[test]
+    fn matches_routes_under_prefixes() {
+        let mut index = RouteIndex::new(Encoding::Utf16);
+        let views = Uri::from_str("file:///app/views.py").unwrap();
+        index.update(
+            &views,
+            "bp = Blueprint('c', __name__, url_prefix='/contacts')\n@bp.get('/<int:id>')\ndef show(id): pass",
+        );
+        assert_eq!(index.matching("/contacts/1").count(), 1);
+        assert_eq!(index.matching("/1").count(), 1);
+        assert_eq!(index.matching("/other/1").count(), 0);
+        assert!(!index.has_unknown_prefixes());
+        let app = Uri::from_str("file:///app/app.py").unwrap();
+        index.update(
+            &app,
+            "app.register_blueprint(bp, url_prefix=settings.PREFIX)",
+        );
+        assert!(index.has_unknown_prefixes());
+    }
+
+    #[test]
+    fn lenses_count_the_elements_requesting_each_handler() {
+        let mut index = RouteIndex::new(Encoding::Utf16);
+        let app = Uri::from_str("file:///app/app.py").unwrap();
+        index.update(
+            &app,
+            "@app.get('/contacts')\ndef contacts(): pass\n@app.delete('/contacts/<int:id>')\ndef delete(id): pass",
+        );
+        let store = TextStore::with_files([(
+            Uri::from_str("file:///app/templates/index.html").unwrap(),
+            r#"<a hx-get="/contacts"></a><input hx-get="/contacts?q=1">"#,
+        )]);
+        let lenses = index.code_lenses(&app, &store);
+        let titles: Vec<&str> = lenses
+            .iter()
+            .filter_map(|lens| Some(lens.command.as_ref()?.title.as_str()))
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                "requested by 2 htmx elements in index.html",
+                "not requested by any htmx element"
+            ]
+        );
+        assert!(lenses
+            .iter()
+            .all(|lens| lens.command.as_ref().is_some_and(|c| c.command.is_empty())));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::textstore::FileData;
+
+/// The keywords that start relative selectors, followed by a CSS selector
+pub const RELATIVE_KEYWORDS: &[&str] = &["closest", "find", "next", "previous"];
+
+/// An htmx extended CSS selector, as accepted by `hx-target`, `hx-include`, `hx-indicator`, etc
+///
+/// See <https://htmx.org/docs/#extended-css-selectors>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtendedSelector<'a> {
+    /// `this`, the element the attribute is on
+    This,
+    /// `document`
+    Document,
+    /// `window`
+    Window,
+    /// `closest <selector>`, the closest ancestor matching the selector
+    Closest(&'a str),
+    /// `find <selector>`, the first descendant matching the selector
+    Find(&'a str),
+    /// `next` or `next <selector>`
+    Next(Option<&'a str>),
+    /// `previous` or `previous <selector>`
+    Previous(Option<&'a str>),
+    /// A plain CSS selector, which is resolved against the whole document
+    Css(&'a str),
+}
+
+impl<'a> ExtendedSelector<'a> {
+    /// Parses an extended selector. Anything that isn't one of the htmx keywords is taken to be plain CSS
+    pub fn parse(s: &'a str) -> Self {
+        let s = s.trim();
+        // Hyperscript style query literals, e.g. `<.foo/>`
+        let unwrap = |sel: &'a str| {
+            sel.trim()
+                .strip_prefix('<')
+                .and_then(|sel| sel.strip_suffix("/>"))
+                .unwrap_or(sel)
+                .trim()
+        };
+        let (keyword, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = unwrap(rest);
+        match keyword {
+            "this" if rest.is_empty() => Self::This,
+            "document" if rest.is_empty() => Self::Document,
+            "window" if rest.is_empty() => Self::Window,
+            "closest" => Self::Closest(rest),
+            "find" => Self::Find(rest),
+            "next" => Self::Next(Some(rest).filter(|r| !r.is_empty())),
+            "previous" => Self::Previous(Some(rest).filter(|r| !r.is_empty())),
+            _ => Self::Css(unwrap(s)),
+        }
+    }
+
+    /// Returns the selector to be resolved against the whole document, if this selector is not relative
+    /// to the element it is declared on
+    pub fn absolute(&self) -> Option<&'a str> {
+        match self {
+            Self::Css(css) => Some(css),
+            _ => None,
+        }
+    }
+
+    /// Returns the indices of the nodes this selector picks in `file`, relative to the node at index
+    /// `node`
+    ///
+    /// Returns `None` for `document` and `window`, and for CSS that `tl` can't parse
+    pub fn resolve(&self, file: &FileData, node: usize) -> Option<Vec<usize>> {
+        // Sibling elements after `node`, closest first
+        let siblings = |before: bool| {
+            let parent = file.parents.get(node).copied().flatten();
+            let mut siblings: Vec<usize> = file
+                .tags()
+                .map(|(i, _)| i)
+                .filter(|&i| file.parents.get(i).copied().flatten() == parent)
+                .filter(|&i| if before { i < node } else { i > node })
+                .collect();
+            if before {
+                siblings.reverse();
+            }
+            siblings
+        };
+        Some(match *self {
+            Self::This => vec![node],
+            Self::Document | Self::Window => return None,
+            Self::Css(css) => file.select(css)?,
+            Self::Closest(css) => {
+                let matches = file.select(css)?;
+                file.ancestors(node)
+                    .find(|i| matches.contains(i))
+                    .into_iter()
+                    .collect()
+            }
+            Self::Find(css) => {
+                let matches = file.select(css)?;
+                matches
+                    .into_iter()
+                    .filter(|&i| i != node && file.ancestors(i).any(|a| a == node))
+                    .take(1)
+                    .collect()
+            }
+            Self::Next(None) => siblings(false).into_iter().take(1).collect(),
+            Self::Previous(None) => siblings(true).into_iter().take(1).collect(),
+            Self::Next(Some(css)) => {
+                let matches = file.select(css)?;
+                matches.into_iter().filter(|&i| i > node).take(1).collect()
+            }
+            Self::Previous(Some(css)) => {
+                let matches = file.select(css)?;
+                matches
+                    .into_iter()
+                    .rfind(|&i| i < node)
+                    .into_iter()
+                    .collect()
+            }
+        })
+    }
+}
+
+/// Splits a comma separated list of extended selectors, as accepted by `hx-include`
+///
+/// Commas inside a `<sel/>` query literal don't separate selectors
+pub fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0usize;
+    value
+        .split(move |c| {
+            match c {
+                '<' => depth += 1,
+                '>' => depth = depth.saturating_sub(1),
+                ',' => return depth == 0,
+                _ => {}
+            }
+            false
+        })
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textstore::{attribute, TextStore};
+
+    #[test]
+    fn parses_keywords_and_css() {
+        assert_eq!(ExtendedSelector::parse(" this "), ExtendedSelector::This);
+        assert_eq!(
+            ExtendedSelector::parse("closest tr"),
+            ExtendedSelector::Closest("tr")
+        );
+        assert_eq!(
+            ExtendedSelector::parse("find <.row/>"),
+            ExtendedSelector::Find(".row")
+        );
+        assert_eq!(
+            ExtendedSelector::parse("next"),
+            ExtendedSelector::Next(None)
+        );
+        assert_eq!(
+            ExtendedSelector::parse("previous  .a"),
+            ExtendedSelector::Previous(Some(".a"))
+        );
+        assert_eq!(
+            ExtendedSelector::parse("#main"),
+            ExtendedSelector::Css("#main")
+        );
+        assert_eq!(
+            ExtendedSelector::parse("<#main/>"),
+            ExtendedSelector::Css("#main")
+        );
+        assert_eq!(
+            ExtendedSelector::parse("this p"),
+            ExtendedSelector::Css("this p")
+        );
+        assert_eq!(ExtendedSelector::parse("#main").absolute(), Some("#main"));
+        assert_eq!(ExtendedSelector::parse("closest tr").absolute(), None);
+    }
+
+    #[test]
+    fn splits_lists_outside_query_literals() {
+        let items: Vec<&str> =
+            split_list(" #a, closest form ,, <input[name='a'], [name='b']/>").collect();
+        assert_eq!(
+            items,
+            ["#a", "closest form", "<input[name='a'], [name='b']/>"]
+        );
+    }
+
+    #[test]
+    fn resolves_relative_to_the_element() {
+        let html = r#"<form id="f"><div><input name="a"><button id="b"></button><input name="c"></div></form>"#;
+        let store = TextStore::with_page(html);
+        let file = store.files.values().next().unwrap();
+        let index = |id: &str| {
+            file.tags()
+                .find(|(_, tag)| {
+                    attribute(tag, "id") == Some(id) || attribute(tag, "name") == Some(id)
+                })
+                .unwrap()
+                .0
+        };
+        let button = index("b");
+        let resolve = |s: &str| ExtendedSelector::parse(s).resolve(file, button);
+        assert_eq!(resolve("this"), Some(vec![button]));
+        assert_eq!(resolve("closest form"), Some(vec![index("f")]));
+        assert_eq!(resolve("next"), Some(vec![index("c")]));
+        assert_eq!(resolve("previous input"), Some(vec![index("a")]));
+        assert_eq!(resolve("find input"), Some(vec![]));
+        assert_eq!(resolve("document"), None);
+    }
+}
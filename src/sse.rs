@@ -0,0 +1,195 @@
+//! The Server Sent Events extension, which connects to an event source with `sse-connect` and swaps the
+//! events it sends into the elements inside with `sse-swap`, or triggers requests on them with
+//! `hx-trigger="sse:<event>"`
+
+use itertools::Itertools;
+use lsp_types::{CompletionItem, CompletionItemKind, Diagnostic};
+
+use crate::{
+    diagnostics::diagnostic,
+    textstore::{attribute, str_ptr_offset, FileData},
+    values::{completion, list_item},
+};
+
+/// The event sent by sources that don't name their events
+const DEFAULT_EVENT: &str = "message";
+
+/// Returns the events `hx-trigger` listens to on the event source, written as `sse:<event>`
+fn trigger_events(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .filter_map(|trigger| trigger.split_whitespace().next()?.strip_prefix("sse:"))
+}
+
+/// Returns the events the tag at index `i` swaps in, triggers requests on or closes the connection on
+fn events(file: &FileData, i: usize) -> Vec<&str> {
+    let Some(tag) = file.dom.nodes().get(i).and_then(tl::Node::as_tag) else {
+        return Vec::new();
+    };
+    let swapped = attribute(tag, "sse-swap")
+        .into_iter()
+        .flat_map(|value| value.split(',').map(str::trim));
+    let triggers = attribute(tag, "hx-trigger")
+        .into_iter()
+        .flat_map(trigger_events);
+    swapped
+        .chain(triggers)
+        .chain(attribute(tag, "sse-close").map(str::trim))
+        .filter(|event| !event.is_empty() && !file.is_templated(event))
+        .collect()
+}
+
+/// Returns the index of the closest element with `sse-connect`, starting at the tag at index `i`
+fn connection(file: &FileData, i: usize) -> Option<usize> {
+    file.ancestors(i).find(|&i| {
+        file.dom
+            .nodes()
+            .get(i)
+            .and_then(tl::Node::as_tag)
+            .is_some_and(|tag| attribute(tag, "sse-connect").is_some())
+    })
+}
+
+/// Checks that elements receiving events are inside an element connected to an event source
+///
+/// A file that is part of a page connecting in one of `layouts` may be inside the connected element, so
+/// it isn't checked
+pub fn check(file: &FileData, layouts: &[&FileData]) -> Vec<Diagnostic> {
+    let connected = layouts
+        .iter()
+        .filter(|layout| !std::ptr::eq(**layout, file))
+        .flat_map(|layout| layout.attributes())
+        .any(|(_, key, _)| key.eq_ignore_ascii_case("sse-connect"));
+    let mut diagnostics = Vec::new();
+    for (i, tag) in file.tags() {
+        if let Some(value) = attribute(tag, "hx-trigger").filter(|v| !file.is_templated(v)) {
+            for trigger in value.split(',') {
+                let Some(event) = trigger.split_whitespace().next() else {
+                    continue;
+                };
+                if event == "sse:" {
+                    diagnostics.push(diagnostic(
+                        file,
+                        event,
+                        "hx-trigger: expected an event name after `sse:`".into(),
+                    ));
+                } else if event.starts_with("sse:") && !connected && connection(file, i).is_none() {
+                    diagnostics.push(diagnostic(
+                        file,
+                        event,
+                        format!(
+                            "hx-trigger: `{event}` needs an element with `sse-connect` around it"
+                        ),
+                    ));
+                }
+            }
+        }
+        let Some(value) = attribute(tag, "sse-swap") else {
+            continue;
+        };
+        if !connected && connection(file, i).is_none() {
+            diagnostics.push(diagnostic(
+                file,
+                value,
+                "sse-swap needs an element with `sse-connect` around it to receive events from"
+                    .into(),
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// Completes the event names used in the same scope as the tag at index `i`, which is the closest
+/// element with `sse-connect` or the whole file, leaving out `current`, the name being completed
+fn complete_events(file: &FileData, i: usize, current: &str) -> Vec<CompletionItem> {
+    let scope = connection(file, i);
+    let range = file.range_of(current);
+    file.tags()
+        .map(|(j, _)| j)
+        .filter(|&j| scope.is_none_or(|scope| file.ancestors(j).any(|a| a == scope)))
+        .flat_map(|j| events(file, j))
+        .filter(|event| event.as_ptr() != current.as_ptr())
+        .chain([DEFAULT_EVENT])
+        .unique()
+        .map(|event| completion(event, CompletionItemKind::EVENT, range))
+        .collect()
+}
+
+/// Completes the event under `cursor` in the value of `key`, which is `value`
+///
+/// `hx-trigger` is only completed after `sse:`, returning `None` otherwise
+pub fn completions(
+    file: &FileData,
+    key: &str,
+    value: &str,
+    cursor: usize,
+) -> Option<Vec<CompletionItem>> {
+    let i = file.tag_of(value)?;
+    let (start, end) = list_item(value, cursor);
+    match key {
+        "sse-swap" | "sse-close" => Some(complete_events(file, i, &value[start..end])),
+        "hx-trigger" => {
+            let event = value[start..end].split_whitespace().next()?;
+            let name = event.strip_prefix("sse:")?;
+            let offset = str_ptr_offset(value, event) + "sse:".len();
+            (cursor >= offset && cursor <= offset + name.len())
+                .then(|| complete_events(file, i, name))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textstore::TextStore;
+
+    /// Completes the value of `key` in `html`, at the cursor marked with `|`
+    fn complete(html: &str, key: &str) -> Option<Vec<String>> {
+        let cursor = html.find('|').unwrap();
+        let store = TextStore::with_page(&html.replace('|', ""));
+        let file = store.files.values().next().unwrap();
+        let (_, _, value) = file.attributes().find(|(_, k, _)| *k == key).unwrap();
+        let offset = cursor - str_ptr_offset(&file.data, value);
+        let items = completions(file, key, value, offset)?;
+        Some(items.into_iter().map(|item| item.label).collect())
+    }
+
+    #[test]
+    fn reads_sse_events_from_triggers() {
+        let events: Vec<&str> = trigger_events("sse:chat once, click, sse:close").collect();
+        assert_eq!(events, ["chat", "close"]);
+    }
+
+    #[test]
+    fn receivers_need_a_connection() {
+        let store = TextStore::with_page(
+            r#"<div sse-connect="/events"><p sse-swap="chat"></p></div>
+<p sse-swap="news"></p><button hx-trigger="sse:news" hx-get="/"></button><i hx-trigger="sse:"></i>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let messages: Vec<String> = check(file, &[]).into_iter().map(|d| d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "sse-swap needs an element with `sse-connect` around it to receive events from",
+                "hx-trigger: `sse:news` needs an element with `sse-connect` around it",
+                "hx-trigger: expected an event name after `sse:`",
+            ]
+        );
+    }
+
+    #[test]
+    fn completes_events_in_the_same_connection() {
+        let html = r#"<div sse-connect="/a"><p sse-swap="chat"></p><p hx-trigger="sse:|"></p></div>
+<div sse-connect="/b"><p sse-swap="news"></p></div>"#;
+        assert_eq!(complete(html, "hx-trigger").unwrap(), ["chat", "message"]);
+        let html =
+            r#"<div sse-connect="/a"><p sse-swap="chat, |"></p><p sse-close="done"></p></div>"#;
+        assert_eq!(
+            complete(html, "sse-swap").unwrap(),
+            ["chat", "done", "message"]
+        );
+        assert!(complete(r#"<p hx-trigger="cl|ick"></p>"#, "hx-trigger").is_none());
+    }
+}
@@ -0,0 +1,423 @@
+use std::{ops::Range, path::Path};
+
+use crate::embedded;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefKind {
+    /// `{% extends 'name' %}`
+    Extends,
+    /// `{% include 'name' %}`, `{{ template "name" }}`, `{{> name }}`, etc
+    Include,
+}
+
+/// A reference from one template to another
+#[derive(Debug, Clone)]
+pub struct TemplateRef {
+    pub kind: RefKind,
+    /// The name of the referenced template, as written in the source
+    pub name: String,
+}
+
+/// Source text with all template syntax blanked out
+#[derive(Debug)]
+pub struct Masked {
+    pub text: String,
+    /// Byte ranges (in both the original and masked text) that were blanked out
+    pub spans: Vec<Range<usize>>,
+    /// Other templates pulled in by this one
+    pub references: Vec<TemplateRef>,
+}
+
+impl Masked {
+    /// Blanks out `spans` of `src`
+    ///
+    /// Masked bytes are replaced with spaces, except for line breaks, which are kept. This preserves both
+    /// byte offsets and line numbers, so offsets into the masked text are also valid offsets into `src`.
+    /// Spans that make up a whole unquoted attribute value, like `id={{ id }}`, become a quoted blank
+    /// value instead, so that the HTML parser doesn't take the next attribute as the value
+    pub fn new(src: &str, spans: Vec<Range<usize>>, references: Vec<TemplateRef>) -> Self {
+        let mut text = src.as_bytes().to_vec();
+        for span in &spans {
+            for b in &mut text[span.clone()] {
+                if !matches!(b, b'\n' | b'\r') {
+                    *b = b' ';
+                }
+            }
+            let is_value = span.start > 0
+                && src.as_bytes()[span.start - 1] == b'='
+                && !in_quotes(&text[..span.start]);
+            if is_value && span.len() >= 2 && !matches!(text[span.end - 1], b'\n' | b'\r') {
+                text[span.start] = b'"';
+                text[span.end - 1] = b'"';
+            }
+        }
+        // Spans start and end at ASCII delimiters, so whole characters are always replaced
+        let text = String::from_utf8(text).expect("Masking should preserve utf8");
+        Self {
+            text,
+            spans,
+            references,
+        }
+    }
+}
+
+/// Returns whether the end of `text` is inside a quoted attribute value of the tag it is in
+///
+/// Template tags before it are already blanked out, so their quotes aren't counted
+fn in_quotes(text: &[u8]) -> bool {
+    let tag = text.iter().rposition(|&b| b == b'<').map_or(0, |i| i + 1);
+    let mut quote = None;
+    for &b in &text[tag..] {
+        match quote {
+            None if matches!(b, b'"' | b'\'') => quote = Some(b),
+            Some(q) if q == b => quote = None,
+            _ => {}
+        }
+    }
+    quote.is_some()
+}
+
+/// The syntax of a templating engine, or of a host language that HTML is embedded in
+pub trait Dialect: Sync + std::fmt::Debug {
+    /// Opening and closing delimiters of template tags
+    ///
+    /// These are tried in order, so a delimiter must come before any of its prefixes
+    fn delimiters(&self) -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    /// Prefixes of the template tags that are comments, whose contents may contain unbalanced quotes
+    fn comments(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Parses the contents of a template tag opened with `open` into a reference to another template
+    fn reference(&self, _open: &str, _body: &str) -> Option<TemplateRef> {
+        None
+    }
+
+    /// Blanks out everything in `src` that isn't HTML, so that it can be handed to an HTML parser
+    ///
+    /// By default, this masks every template tag
+    fn mask(&self, src: &str) -> Masked {
+        let mut spans = Vec::new();
+        let mut references = Vec::new();
+        let mut i = 0;
+        while let Some(found) = src[i..].find('{') {
+            let start = i + found;
+            let Some((open, close)) = self
+                .delimiters()
+                .iter()
+                .find(|(open, _)| src[start..].starts_with(open))
+            else {
+                i = start + 1;
+                continue;
+            };
+            let is_comment = self
+                .comments()
+                .iter()
+                .any(|comment| src[start..].starts_with(comment));
+            let end = tag_end(src, start + open.len(), close, !is_comment);
+            if let Some(body) = src[start + open.len()..end].strip_suffix(close) {
+                references.extend(self.reference(open, body));
+            }
+            spans.push(start..end);
+            i = end;
+        }
+        Masked::new(src, spans, references)
+    }
+}
+
+/// Jinja, Django, Askama, Tera, Nunjucks and Twig, which all share the same syntax for our purposes
+///
+/// `{{ ... }}` are expressions, `{% ... %}` are statements and `{# ... #}` are comments
+#[derive(Debug)]
+pub struct Jinja;
+
+impl Dialect for Jinja {
+    fn delimiters(&self) -> &'static [(&'static str, &'static str)] {
+        &[("{{", "}}"), ("{%", "%}"), ("{#", "#}")]
+    }
+
+    fn comments(&self) -> &'static [&'static str] {
+        &["{#"]
+    }
+
+    fn reference(&self, open: &str, body: &str) -> Option<TemplateRef> {
+        if open != "{%" {
+            return None;
+        }
+        let (keyword, rest) = trim_tag(body).split_once(char::is_whitespace)?;
+        let kind = match keyword {
+            "extends" => RefKind::Extends,
+            "include" => RefKind::Include,
+            _ => return None,
+        };
+        Some(TemplateRef {
+            kind,
+            name: quoted(rest)?.to_string(),
+        })
+    }
+}
+
+/// Go's `text/template` and `html/template`
+///
+/// Everything is in `{{ ... }}`, and other templates are pulled in with `{{ template "name" . }}`
+#[derive(Debug)]
+pub struct GoTemplate;
+
+impl Dialect for GoTemplate {
+    fn delimiters(&self) -> &'static [(&'static str, &'static str)] {
+        &[("{{", "}}")]
+    }
+
+    fn comments(&self) -> &'static [&'static str] {
+        &["{{/*", "{{- /*"]
+    }
+
+    fn reference(&self, _open: &str, body: &str) -> Option<TemplateRef> {
+        let (keyword, rest) = trim_tag(body).split_once(char::is_whitespace)?;
+        if !matches!(keyword, "template" | "block") {
+            return None;
+        }
+        Some(TemplateRef {
+            kind: RefKind::Include,
+            name: quoted(rest)?.to_string(),
+        })
+    }
+}
+
+/// Handlebars and Mustache
+///
+/// Partials are pulled in with `{{> name }}`, or `{{#> name }}` for partial blocks
+#[derive(Debug)]
+pub struct Handlebars;
+
+impl Dialect for Handlebars {
+    fn delimiters(&self) -> &'static [(&'static str, &'static str)] {
+        &[("{{!--", "--}}"), ("{{{", "}}}"), ("{{", "}}")]
+    }
+
+    fn comments(&self) -> &'static [&'static str] {
+        &["{{!", "{{~!"]
+    }
+
+    fn reference(&self, open: &str, body: &str) -> Option<TemplateRef> {
+        if open != "{{" {
+            return None;
+        }
+        let body = trim_tag(body);
+        let rest = body
+            .strip_prefix('>')
+            .or_else(|| body.strip_prefix("#>"))?
+            .trim_start();
+        let name = quoted(rest).unwrap_or_else(|| {
+            rest.split(|c: char| c.is_whitespace() || c == '}')
+                .next()
+                .unwrap_or_default()
+        });
+        // Dynamic partials like `{{> (lookup . 'name') }}` can't be resolved statically
+        if name.is_empty() || name.starts_with('(') {
+            return None;
+        }
+        Some(TemplateRef {
+            kind: RefKind::Include,
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Picks the template dialect of a document from its `languageId`, falling back to its file extension
+///
+/// Documents in languages HTML can be embedded in get the dialect of their host language. Anything we
+/// don't recognize is treated as Jinja, which is also harmless on plain HTML
+pub fn dialect_for(path: Option<&Path>, language_id: Option<&str>) -> &'static dyn Dialect {
+    let extension = path.and_then(Path::extension).and_then(|ext| ext.to_str());
+    if let Some(host) = embedded::host_for(extension, language_id) {
+        return host;
+    }
+    match (language_id, extension) {
+        (
+            Some("jinja" | "jinja-html" | "django-html" | "twig" | "nunjucks" | "tera" | "askama"),
+            _,
+        ) => &Jinja,
+        (Some("gotmpl" | "gohtml" | "go-html-template"), _)
+        | (_, Some("gohtml" | "gotmpl" | "tmpl")) => &GoTemplate,
+        (Some("handlebars" | "hbs" | "mustache"), _)
+        | (_, Some("hbs" | "handlebars" | "mustache")) => &Handlebars,
+        _ => &Jinja,
+    }
+}
+
+/// Strips whitespace control markers (`{%-`, `-}}`, `~}}` etc) and whitespace from a tag body
+fn trim_tag(body: &str) -> &str {
+    body.trim_matches(|c: char| matches!(c, '-' | '+' | '~') || c.is_whitespace())
+}
+
+/// Returns the contents of the string literal at the start of `s`
+fn quoted(s: &str) -> Option<&str> {
+    let s = s.trim_start();
+    let quote = s.chars().next().filter(|c| matches!(c, '"' | '\'' | '`'))?;
+    let (name, _) = s[1..].split_once(quote)?;
+    Some(name)
+}
+
+/// Finds the end of a template tag starting at `start`, skipping over quoted strings unless `quotes` is
+/// false, as in comments
+///
+/// Returns the offset just past the closing delimiter, or the end of `src` when the tag is unterminated
+fn tag_end(src: &str, start: usize, close: &str, quotes: bool) -> usize {
+    let bytes = src.as_bytes();
+    let mut quote = None;
+    let mut i = start;
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (None, b'"' | b'\'') if quotes => quote = Some(bytes[i]),
+            (Some(q), c) if q == c => quote = None,
+            (None, _) if src[i..].starts_with(close) => return i + close.len(),
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_preserves_offsets_and_lines() {
+        let src = "<p>{{ name }}</p>\n{% if x %}\n<b>{# note #}</b>";
+        let masked = Jinja.mask(src);
+        assert_eq!(masked.text.len(), src.len());
+        assert_eq!(
+            masked.text,
+            "<p>          </p>\n          \n<b>          </b>"
+        );
+        assert_eq!(masked.spans, vec![3..13, 18..28, 32..42]);
+    }
+
+    #[test]
+    fn mask_skips_quoted_delimiters() {
+        let masked = Jinja.mask("{{ '}}' }}<p></p>");
+        assert_eq!(masked.text, "          <p></p>");
+    }
+
+    #[test]
+    fn mask_ignores_quotes_in_comments() {
+        let masked = Jinja.mask("{# don't #}<p id=\"a\"></p>{{ x }}");
+        assert_eq!(masked.text, "           <p id=\"a\"></p>       ");
+        let masked = Handlebars.mask("{{!-- it's --}}<p></p>");
+        assert_eq!(masked.text, "               <p></p>");
+        let masked = Handlebars.mask("{{! don't }}<p id=\"a\"></p>");
+        assert_eq!(masked.text, "            <p id=\"a\"></p>");
+        let masked = GoTemplate.mask("{{/* it's */}}<p id=\"a\"></p>{{- /* 'x */ -}}");
+        assert_eq!(
+            masked.text,
+            "              <p id=\"a\"></p>                "
+        );
+    }
+
+    #[test]
+    fn mask_go_templates() {
+        let masked =
+            GoTemplate.mask("<p class=\"{{ if .On }}on{{ end }}\">{{ .Name | printf \"%q\" }}</p>");
+        assert_eq!(
+            masked.text,
+            "<p class=\"            on         \">                         </p>"
+        );
+    }
+
+    #[test]
+    fn mask_quotes_unquoted_values() {
+        let masked = Jinja.mask("<p id={{ id }} class=a></p>");
+        assert_eq!(masked.text, "<p id=\"      \" class=a></p>");
+    }
+
+    #[test]
+    fn mask_keeps_quoted_values_balanced() {
+        let masked = Jinja.mask("<p hx-vals='a={{ a }}' data-x=\"b={{ b }}\"></p>");
+        assert_eq!(
+            masked.text,
+            "<p hx-vals='a=       ' data-x=\"b=       \"></p>"
+        );
+    }
+
+    #[test]
+    fn mask_collects_references() {
+        let masked = Jinja.mask("{% extends 'base.html' %}{% include \"row.html\" %}");
+        let names: Vec<_> = masked
+            .references
+            .iter()
+            .map(|r| (r.kind, r.name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (RefKind::Extends, "base.html"),
+                (RefKind::Include, "row.html")
+            ]
+        );
+    }
+
+    #[test]
+    fn mask_leaves_unterminated_tags_to_the_end() {
+        let masked = Jinja.mask("<p>{{ x</p>");
+        assert_eq!(masked.text, "<p>        ");
+    }
+
+    /// Returns the references collected when masking `src` with `dialect`
+    fn references(dialect: &dyn Dialect, src: &str) -> Vec<(RefKind, String)> {
+        dialect
+            .mask(src)
+            .references
+            .into_iter()
+            .map(|r| (r.kind, r.name))
+            .collect()
+    }
+
+    #[test]
+    fn go_templates_reference_templates_and_blocks() {
+        assert_eq!(
+            references(
+                &GoTemplate,
+                r#"{{ template "row.html" . }}{{- block "nav" . -}}{{ end }}{{ .Template }}"#
+            ),
+            [
+                (RefKind::Include, "row.html".to_string()),
+                (RefKind::Include, "nav".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn handlebars_references_static_partials() {
+        assert_eq!(
+            references(
+                &Handlebars,
+                "{{> row }}{{#> layout title='x' }}{{/layout}}{{> 'quoted name' }}{{> (lookup . 'p') }}{{{> raw }}}"
+            ),
+            [
+                (RefKind::Include, "row".to_string()),
+                (RefKind::Include, "layout".to_string()),
+                (RefKind::Include, "quoted name".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn picks_dialects_by_language_then_extension() {
+        let dialect = |path: &str, language_id| {
+            format!("{:?}", dialect_for(Some(Path::new(path)), language_id))
+        };
+        assert_eq!(dialect("a.html", Some("gotmpl")), "GoTemplate");
+        assert_eq!(dialect("a.tmpl", None), "GoTemplate");
+        assert_eq!(dialect("a.hbs", None), "Handlebars");
+        assert_eq!(dialect("a.html", Some("mustache")), "Handlebars");
+        assert_eq!(dialect("a.hbs", Some("django-html")), "Jinja");
+        assert_eq!(dialect("a.html", None), "Jinja");
+        assert_eq!(dialect("a.py", None), "Python");
+        assert_eq!(dialect("a.txt", Some("rust")), "Rust");
+    }
+}
@@ -1,15 +1,35 @@
 use log::warn;
-use lsp_types::Uri;
-use std::{collections::HashMap, fmt::Pointer};
-use tl::{HTMLTag, ParserOptions, VDom};
+use lsp_types::{Position, Range as LspRange, Uri};
+use std::{collections::HashMap, ops::Range};
+use tl::{ParserOptions, VDom};
+
+use crate::{
+    lineindex::{Encoding, LineIndex},
+    template,
+    workspace::uri_to_path,
+};
 
 #[derive(Debug)]
 pub struct FileData {
+    /// The original contents of the file
+    pub source: Box<str>,
+    /// `source` with templates masked out, which is what `dom` is parsed from
     pub data: Box<str>,
     // We need to cheat a little to get self referential structs to work
     pub dom: VDom<'static>,
-    /// Stores offset to each line in the file
-    pub lines: Vec<usize>,
+    /// Converts between offsets and positions in the file
+    pub lines: LineIndex,
+    /// Byte ranges of template tags, which are masked out of `data` before parsing
+    pub templates: Vec<Range<usize>>,
+    /// Other templates this file extends or includes
+    pub references: Vec<template::TemplateRef>,
+    /// The templating language the file is written in
+    pub dialect: &'static dyn template::Dialect,
+    /// The index of the parent of each node in `dom`
+    pub parents: Vec<Option<usize>>,
+    /// Set when the latest version of the file failed to parse, in which case everything else describes
+    /// the last version that did
+    pub parse_error: Option<tl::ParseError>,
 }
 
 /// A large amount of magic depends on this function
@@ -34,44 +54,207 @@ pub fn str_ptr_offset(str1: &str, str2: &str) -> usize {
 pub enum HTMLObject<'a> {
     Tag(&'a str),
     Attr(&'a str),
-    AttrValue(&'a str),
+    AttrValue { key: &'a str, value: &'a str },
 }
 
 impl FileData {
-    fn new(content: Box<str>) -> Result<Self, tl::ParseError> {
+    fn new(
+        content: &str,
+        dialect: &'static dyn template::Dialect,
+        encoding: Encoding,
+    ) -> Result<Self, tl::ParseError> {
+        let source = Box::from(content);
+        let template::Masked {
+            text,
+            spans,
+            references,
+        } = dialect.mask(content);
+        let content = text.into_boxed_str();
         let inner: &str = &content;
         let dom = tl::parse(
             unsafe { std::mem::transmute::<&str, &'static str>(inner) },
             ParserOptions::new().track_ids().track_classes(),
         )?;
-        let lines = inner.lines().map(|l| str_ptr_offset(inner, l)).collect();
+        let lines = LineIndex::new(&source, encoding);
+        let mut parents = vec![None; dom.nodes().len()];
+        for (i, node) in dom.nodes().iter().enumerate() {
+            for child in node.children().iter().flat_map(|c| c.top().iter()) {
+                parents[child.get_inner() as usize] = Some(i);
+            }
+        }
         Ok(Self {
+            source,
             data: content,
             dom,
             lines,
+            templates: spans,
+            references,
+            dialect,
+            parents,
+            parse_error: None,
+        })
+    }
+
+    /// Converts `pos` to a byte offset, or returns `None` when it is past the end of the file
+    pub fn position_to_offset(&self, pos: Position) -> Option<usize> {
+        self.lines.offset(&self.source, pos)
+    }
+
+    /// Returns the range covered by `s`, which must be a slice of `self.data`
+    pub fn range_of(&self, s: &str) -> LspRange {
+        let start = str_ptr_offset(&self.data, s);
+        // Masked text has the same byte offsets as the source, but not necessarily the same characters
+        self.lines.range(&self.source, start..start + s.len())
+    }
+
+    /// Returns the original text of `s`, which must be a slice of `self.data`, with any templates in it
+    pub fn source_of(&self, s: &str) -> &str {
+        let start = str_ptr_offset(&self.data, s);
+        &self.source[start..start + s.len()]
+    }
+
+    /// Iterates over every attribute with a value in the document, along with the tag it belongs to
+    pub fn attributes(&self) -> impl Iterator<Item = (&tl::HTMLTag<'static>, &str, &str)> {
+        self.dom
+            .nodes()
+            .iter()
+            .filter_map(tl::Node::as_tag)
+            .flat_map(move |tag| {
+                tag.attributes()
+                    .unstable_raw()
+                    .iter()
+                    .filter_map(move |(key, val)| {
+                        Some((
+                            tag,
+                            self.full_key(key.try_as_utf8_str()?),
+                            val.as_ref()?.try_as_utf8_str()?,
+                        ))
+                    })
+            })
+    }
+
+    /// Iterates over every tag in the document, along with its index in `dom`
+    pub fn tags(&self) -> impl Iterator<Item = (usize, &tl::HTMLTag<'static>)> {
+        self.dom
+            .nodes()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| Some((i, node.as_tag()?)))
+    }
+
+    /// Iterates over the node at index `i` and its ancestors, innermost first
+    pub fn ancestors(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(i), |&i| self.parents.get(i).copied().flatten())
+    }
+
+    /// Returns the value of `key` on the tag at index `i` or, as htmx attributes are inherited, on the
+    /// closest ancestor that has it
+    pub fn inherited(&self, i: usize, key: &str) -> Option<&str> {
+        self.ancestors(i).find_map(|i| {
+            let tag = self.dom.nodes().get(i)?.as_tag()?;
+            attribute(tag, key)
         })
     }
 
-    pub fn line_to_offset(&self, line: usize, chr: usize) -> usize {
-        self.lines[line] + chr
+    /// Returns the index of the tag that `s`, which must be a slice of `self.data`, is an attribute name or
+    /// value of
+    pub fn tag_of(&self, s: &str) -> Option<usize> {
+        self.tags()
+            .find(|(_, tag)| {
+                tag.attributes()
+                    .unstable_raw()
+                    .iter()
+                    .flat_map(|(key, value)| std::iter::once(key).chain(value))
+                    .any(|part| part.as_bytes().as_ptr() == s.as_ptr())
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// Iterates over the tags inside the tag at index `i`
+    pub fn descendants(&self, i: usize) -> impl Iterator<Item = &tl::HTMLTag<'static>> {
+        self.dom
+            .nodes()
+            .get(i)
+            .and_then(tl::Node::as_tag)
+            .map_or(&[][..], |tag| tag.children().all(self.dom.parser()))
+            .iter()
+            .filter_map(tl::Node::as_tag)
+    }
+
+    /// Returns the index of the `<form>` the tag at index `i` is in, which may be the tag itself
+    pub fn enclosing_form(&self, i: usize) -> Option<usize> {
+        self.ancestors(i).find(|&i| {
+            self.dom
+                .nodes()
+                .get(i)
+                .and_then(tl::Node::as_tag)
+                .is_some_and(|tag| tag.name().as_bytes().eq_ignore_ascii_case(b"form"))
+        })
+    }
+
+    /// Returns the names of the fields submitted with the form at index `form`
+    ///
+    /// These are the fields inside it, and the ones elsewhere that point at it with their `form`
+    /// attribute
+    pub fn form_fields(&self, form: usize) -> Vec<&str> {
+        let id = self
+            .dom
+            .nodes()
+            .get(form)
+            .and_then(tl::Node::as_tag)
+            .and_then(|tag| attribute(tag, "id"));
+        let outside = self
+            .tags()
+            .map(|(_, tag)| tag)
+            .filter(|tag| id.is_some() && attribute(tag, "form") == id);
+        self.descendants(form)
+            .chain(outside)
+            .filter_map(field_name)
+            .collect()
+    }
+
+    /// Returns the indices of the nodes matching the CSS `selector`, or `None` if `tl` can't parse it
+    pub fn select(&self, selector: &str) -> Option<Vec<usize>> {
+        Some(
+            self.dom
+                .query_selector(selector)?
+                .map(|handle| handle.get_inner() as usize)
+                .collect(),
+        )
+    }
+
+    /// Returns whether including the element at index `i` in a request submits any values, which is the
+    /// case for forms, named fields, and elements with named fields inside them
+    pub fn submits_values(&self, i: usize) -> bool {
+        let Some(tag) = self.dom.nodes().get(i).and_then(tl::Node::as_tag) else {
+            return false;
+        };
+        tag.name().as_bytes().eq_ignore_ascii_case(b"form")
+            || field_name(tag).is_some()
+            || self.descendants(i).any(|tag| field_name(tag).is_some())
     }
 
     /// Returns the particular object(tag, attribute, or attribute value) under the cursor
     ///
     /// Returns `None` when `&self` has no tags or when the tag names are not proper utf8
-    pub fn object_under_cursor(&self, off: usize) -> Option<HTMLObject> {
+    pub fn object_under_cursor(&self, off: usize) -> Option<HTMLObject<'_>> {
+        if self.in_template(off) {
+            return None;
+        }
         // Find node under `off`
         let curr_node = self.dom.nodes().binary_search_by_key(&off, |n| {
             let bytes = match n {
                 tl::Node::Tag(htmltag) => htmltag.raw(),
                 tl::Node::Raw(bytes) | tl::Node::Comment(bytes) => bytes,
             };
-            str_ptr_offset(&self.data, bytes.try_as_utf8_str().unwrap())
+            // Nodes always borrow from `self.data`
+            (bytes.as_bytes().as_ptr() as usize).saturating_sub(self.data.as_ptr() as usize)
         });
         warn!("Found {:?}", curr_node);
         let i = match curr_node {
             Ok(i) => i,
-            Err(i) => i - 1,
+            // `off` is before the first node
+            Err(i) => i.checked_sub(1)?,
         };
         if let tl::Node::Tag(tag) = &self.dom.nodes()[i] {
             // See if any attribute key matches
@@ -80,35 +263,204 @@ impl FileData {
                 .unstable_raw()
                 .iter()
                 .find_map(|(key, val)| {
-                    let key = key.try_as_utf8_str().unwrap();
-                    let val = val.as_ref()?.try_as_utf8_str().unwrap();
+                    let key = self.full_key(key.try_as_utf8_str()?);
                     let key_diff = str_ptr_offset(&self.data, key);
-                    let val_diff = str_ptr_offset(&self.data, val);
                     if (key_diff..(key_diff + key.len())).contains(&off) {
-                        Some(HTMLObject::Attr(key))
-                    } else if (val_diff..(val_diff + val.len())).contains(&off) {
-                        Some(HTMLObject::AttrValue(val))
-                    } else {
-                        None
+                        return Some(HTMLObject::Attr(key));
                     }
+                    // Attributes without a value, like one that is still being typed, only have a key
+                    let value = val.as_ref()?.try_as_utf8_str()?;
+                    let val_diff = str_ptr_offset(&self.data, value);
+                    // The end is included so that the cursor can be at the end of a value
+                    (val_diff..=(val_diff + value.len()))
+                        .contains(&off)
+                        .then_some(HTMLObject::AttrValue { key, value })
                 })
                 .or_else(|| Some(HTMLObject::Tag(tag.name().try_as_utf8_str()?)))
         } else {
             None
         }
     }
+
+    /// Extends `key`, an attribute name as parsed by `tl`, to the whole name in the source
+    ///
+    /// `tl` stops attribute names at characters like `@` and `.`, which split Alpine directives like
+    /// `@click.outside` into several attributes
+    fn full_key<'a>(&'a self, key: &'a str) -> &'a str {
+        let is_key =
+            |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '@' | '.');
+        let start = str_ptr_offset(&self.data, key);
+        let end = start + key.len();
+        let start = self.data[..start]
+            .rfind(|c| !is_key(c))
+            .map_or(0, |i| i + 1);
+        let end = self.data[end..]
+            .find(|c| !is_key(c))
+            .map_or(self.data.len(), |i| end + i);
+        &self.data[start..end]
+    }
+
+    /// Returns whether `off` is inside a template tag
+    pub fn in_template(&self, off: usize) -> bool {
+        self.templates.iter().any(|span| span.contains(&off))
+    }
+
+    /// Returns whether any part of `s`, which must be a slice of `self.data`, was produced by a template
+    ///
+    /// Such values can't be checked statically
+    pub fn is_templated(&self, s: &str) -> bool {
+        let start = str_ptr_offset(&self.data, s);
+        let end = start + s.len();
+        self.templates
+            .iter()
+            .any(|span| span.start < end && start < span.end)
+    }
+}
+
+/// Elements whose values are submitted with a form
+const FIELD_TAGS: &[&str] = &["input", "select", "textarea", "button"];
+
+/// Returns the name `tag` submits its value under, if it is a named form field
+pub fn field_name<'a>(tag: &'a tl::HTMLTag) -> Option<&'a str> {
+    let name = tag.name().as_bytes();
+    FIELD_TAGS
+        .iter()
+        .any(|t| name.eq_ignore_ascii_case(t.as_bytes()))
+        .then(|| attribute(tag, "name"))
+        .flatten()
+}
+
+/// Returns the value of `key` on `tag`, if it has one
+pub fn attribute<'a>(tag: &'a tl::HTMLTag, key: &str) -> Option<&'a str> {
+    let attributes = tag.attributes();
+    let value = match key {
+        // `tl` keeps these apart from the other attributes
+        "id" => attributes.id(),
+        "class" => attributes.class(),
+        _ => attributes
+            .unstable_raw()
+            .iter()
+            .find(|(k, _)| k.as_bytes() == key.as_bytes())?
+            .1
+            .as_ref(),
+    };
+    value?.try_as_utf8_str()
 }
 
-pub struct TextStore(pub HashMap<Uri, FileData>);
+pub struct TextStore {
+    pub files: HashMap<Uri, FileData>,
+    /// The position encoding negotiated with the client
+    pub encoding: Encoding,
+}
 
 impl TextStore {
-    pub fn new() -> Self {
-        Self(HashMap::new())
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            files: HashMap::new(),
+            encoding,
+        }
+    }
+
+    /// Inserts or replaces the file at `uri`
+    ///
+    /// `language_id` selects the template dialect. Without it, the dialect the file was previously
+    /// opened with is kept, or guessed from the file extension for new files
+    ///
+    /// When `content` fails to parse, the last version of the file that parsed is kept around, along
+    /// with the error, so that it can still be used and the error reported
+    pub fn insert(&mut self, uri: Uri, content: &str, language_id: Option<&str>) {
+        let dialect = match (language_id, self.files.get(&uri)) {
+            (None, Some(file)) => file.dialect,
+            _ => template::dialect_for(uri_to_path(&uri).as_deref(), language_id),
+        };
+        let file = match FileData::new(content, dialect, self.encoding) {
+            Ok(file) => file,
+            Err(err) => {
+                warn!("Failed to parse {uri:?}: {err}");
+                let last_good = match self.files.remove(&uri) {
+                    Some(file) => file,
+                    None => {
+                        FileData::new("", dialect, self.encoding).expect("Empty files always parse")
+                    }
+                };
+                FileData {
+                    parse_error: Some(err),
+                    ..last_good
+                }
+            }
+        };
+        self.files.insert(uri, file);
+    }
+}
+
+#[cfg(test)]
+impl TextStore {
+    /// Parses `files` into a store holding them alone
+    pub fn with_files<'a>(files: impl IntoIterator<Item = (Uri, &'a str)>) -> Self {
+        let mut store = Self::new(Encoding::Utf16);
+        for (uri, text) in files {
+            store.insert(uri, text, None);
+        }
+        store
+    }
+
+    /// Parses `html` into a store holding it alone, as `file:///page.html`
+    pub fn with_page(html: &str) -> Self {
+        use std::str::FromStr;
+
+        Self::with_files([(Uri::from_str("file:///page.html").unwrap(), html)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns what is under the cursor marked with `|` in `html`
+    fn under_cursor(html: &str) -> Option<String> {
+        let cursor = html.find('|').unwrap();
+        let store = TextStore::with_page(&html.replace('|', ""));
+        let file = store.files.values().next().unwrap();
+        file.object_under_cursor(cursor).map(|object| match object {
+            HTMLObject::Tag(name) => format!("tag {name}"),
+            HTMLObject::Attr(key) => format!("attr {key}"),
+            HTMLObject::AttrValue { key, value } => format!("value {key}={value}"),
+        })
+    }
+
+    #[test]
+    fn finds_objects_under_the_cursor() {
+        assert_eq!(
+            under_cursor(r#"<div hx-g|et="/a"></div>"#).unwrap(),
+            "attr hx-get"
+        );
+        assert_eq!(
+            under_cursor(r#"<div hx-get="/|a"></div>"#).unwrap(),
+            "value hx-get=/a"
+        );
+        assert_eq!(under_cursor("<div h|x-></div>").unwrap(), "attr hx-");
+        assert_eq!(under_cursor(r#"<d|iv id="a"></div>"#).unwrap(), "tag div");
+    }
+
+    #[test]
+    fn nothing_is_under_the_cursor_outside_of_tags() {
+        assert!(under_cursor("|").is_none());
+        assert!(under_cursor(r#"<p id="{{ |x }}"></p>"#).is_none());
     }
 
-    pub fn insert(&mut self, uri: Uri, content: &str) {
-        let content = Box::from(content);
-        self.0
-            .insert(uri, FileData::new(content).expect("Failed to parse file"));
+    #[test]
+    fn form_fields_include_fields_pointing_at_the_form() {
+        let store = TextStore::with_page(
+            r#"<form id="f"><input name="a"><select name="b"></select><input></form><textarea name="c" form="f"></textarea><input name="d">"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let form = file.tags().find(|(_, tag)| tag.name() == "form").unwrap().0;
+        assert_eq!(file.form_fields(form), ["a", "b", "c"]);
+        let input = file
+            .tags()
+            .find(|(_, tag)| attribute(tag, "name") == Some("a"))
+            .unwrap()
+            .0;
+        assert_eq!(file.enclosing_form(input), Some(form));
     }
 }
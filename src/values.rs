@@ -0,0 +1,938 @@
+//! Checks and completions for the values of individual htmx attributes
+
+use std::borrow::Cow;
+
+use itertools::Itertools;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    Documentation, Hover, HoverContents, MarkupContent, MarkupKind, Range, TextEdit,
+};
+use phf::phf_map;
+
+use crate::{
+    alpine,
+    config::{Config, Library},
+    datastar,
+    diagnostics::diagnostic,
+    extensions, htmx, hyperscript,
+    selector::{ExtendedSelector, RELATIVE_KEYWORDS},
+    sse,
+    textstore::{attribute, field_name, str_ptr_offset, FileData},
+};
+
+/// The value of `hx-params`, which filters the parameters submitted with a request
+#[derive(Debug, PartialEq, Eq)]
+enum Params<'a> {
+    /// `*`
+    All,
+    /// `none`
+    None,
+    /// `not a, b`
+    Not(Vec<&'a str>),
+    /// `a, b`
+    Only(Vec<&'a str>),
+}
+
+impl<'a> Params<'a> {
+    const ALL: &'static str = "*";
+    const NONE: &'static str = "none";
+    const NOT: &'static str = "not ";
+
+    fn parse(value: &'a str) -> Self {
+        let value = value.trim();
+        match value {
+            Self::ALL => Self::All,
+            Self::NONE => Self::None,
+            _ => match value.strip_prefix(Self::NOT) {
+                Some(names) => Self::Not(names.split(',').map(str::trim).collect()),
+                None => Self::Only(value.split(',').map(str::trim).collect()),
+            },
+        }
+    }
+
+    fn names(&self) -> &[&'a str] {
+        match self {
+            Self::All | Self::None => &[],
+            Self::Not(names) | Self::Only(names) => names,
+        }
+    }
+}
+
+/// The strategies `hx-sync` accepts after the selector, `queue` alone meaning `queue last`
+const SYNC_STRATEGIES: &[&str] = &[
+    "drop",
+    "abort",
+    "replace",
+    "queue",
+    "queue first",
+    "queue last",
+    "queue all",
+];
+
+/// The values accepted by an attribute that doesn't take free form text
+enum ValueType {
+    /// `true` or `false`
+    Boolean,
+    /// `true`, `false` or a url
+    BooleanOrUrl,
+    /// Anything, since only the presence of the attribute matters. `true` is completed
+    Presence,
+    /// One of the listed values
+    Enum(&'static [&'static str]),
+    /// `*` or a space separated list of htmx attributes
+    Attributes,
+}
+
+impl ValueType {
+    /// Returns the parts of `value` that aren't accepted, which is either the whole value or the
+    /// unknown attributes in a list
+    fn invalid<'v>(&self, value: &'v str) -> Vec<&'v str> {
+        let accepted = match self {
+            Self::Boolean => matches!(value, "true" | "false"),
+            Self::BooleanOrUrl | Self::Presence => true,
+            Self::Enum(values) => values.contains(&value),
+            Self::Attributes if value.trim() == "*" => true,
+            Self::Attributes => {
+                return value
+                    .split_whitespace()
+                    .filter(|name| !htmx::ATTRIBUTES.contains(name))
+                    .collect()
+            }
+        };
+        if accepted {
+            vec![]
+        } else {
+            vec![value]
+        }
+    }
+
+    /// Returns the values to complete
+    fn values(&self) -> &'static [&'static str] {
+        match self {
+            Self::Boolean | Self::BooleanOrUrl => &["true", "false"],
+            Self::Enum(values) => values,
+            Self::Presence => &["true"],
+            Self::Attributes => &["*"],
+        }
+    }
+
+    /// Describes the accepted values, for diagnostics
+    fn expected(&self) -> String {
+        match self {
+            Self::Boolean | Self::BooleanOrUrl => "`true` or `false`".into(),
+            Self::Enum(values) => values
+                .iter()
+                .map(|v| format!("`{v}`"))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            Self::Presence => "any value".into(),
+            Self::Attributes => "`*` or an htmx attribute".into(),
+        }
+    }
+}
+
+/// The htmx attributes whose values are checked and completed against a [`ValueType`]
+static VALUE_TYPES: phf::Map<&'static str, ValueType> = phf_map! {
+    "hx-boost" => ValueType::Boolean,
+    "hx-push-url" => ValueType::BooleanOrUrl,
+    "hx-replace-url" => ValueType::BooleanOrUrl,
+    "hx-validate" => ValueType::Boolean,
+    "hx-preserve" => ValueType::Presence,
+    "hx-history" => ValueType::Boolean,
+    "hx-disinherit" => ValueType::Attributes,
+    "hx-inherit" => ValueType::Attributes,
+    "hx-encoding" => ValueType::Enum(&["multipart/form-data", "application/x-www-form-urlencoded"]),
+};
+
+/// Splits the value of `hx-sync`, `<selector>:<strategy>`, into its selector and strategy
+///
+/// The value is split at the first colon like htmx does, and the strategy defaults to `drop` when
+/// left out
+pub fn split_sync(value: &str) -> (&str, Option<&str>) {
+    match value.split_once(':') {
+        Some((selector, strategy)) => (selector.trim(), Some(strategy.trim())),
+        None => (value.trim(), None),
+    }
+}
+
+/// HTML entities that are decoded in attribute values holding JSON, which often quote it with them
+const ENTITIES: &[(&str, char)] = &[
+    ("&quot;", '"'),
+    ("&#34;", '"'),
+    ("&#x22;", '"'),
+    ("&apos;", '\''),
+    ("&#39;", '\''),
+    ("&amp;", '&'),
+    ("&lt;", '<'),
+    ("&gt;", '>'),
+];
+
+/// Decodes the entities in `raw`, returning the decoded text along with the offset in `raw` of each of
+/// its bytes, followed by the length of `raw`
+fn decode_entities(raw: &str) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(raw.len());
+    let mut offsets = Vec::with_capacity(raw.len() + 1);
+    let mut i = 0;
+    while let Some(next) = raw[i..].chars().next() {
+        let (c, len) = ENTITIES
+            .iter()
+            .find(|(entity, _)| raw[i..].starts_with(entity))
+            .map_or((next, next.len_utf8()), |(entity, c)| (*c, entity.len()));
+        offsets.extend(std::iter::repeat_n(i, c.len_utf8()));
+        text.push(c);
+        i += len;
+    }
+    offsets.push(raw.len());
+    (text, offsets)
+}
+
+/// Why the JSON in `hx-vals` or `hx-headers` couldn't be read
+pub enum JsonError {
+    /// The value is valid JSON, but not an object
+    NotAnObject,
+    /// The value isn't valid JSON, because of the character at `span`
+    Invalid {
+        span: std::ops::Range<usize>,
+        message: String,
+    },
+}
+
+/// Returns the byte range of the character in `json` that `err` is at, when parsing it failed
+///
+/// When `wrapped` is set, `json` was parsed with braces around it, which shift the first line by one
+fn error_span(json: &str, err: &serde_json::Error, wrapped: bool) -> std::ops::Range<usize> {
+    // Errors are at a 1 based line and column
+    let line_start: usize = json
+        .split_inclusive('\n')
+        .take(err.line().saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let shift = usize::from(wrapped && err.line() == 1);
+    let mut start = (line_start + err.column().saturating_sub(1))
+        .saturating_sub(shift)
+        .min(json.len());
+    while !json.is_char_boundary(start) {
+        start -= 1;
+    }
+    let end = json[start..]
+        .chars()
+        .next()
+        .map_or(start, |c| start + c.len_utf8());
+    start..end
+}
+
+/// Parses the JSON object in `raw`, the value of `hx-vals` or `hx-headers`, after decoding its entities
+///
+/// Spans in errors are byte ranges of `raw`
+pub fn parse_json_object(
+    raw: &str,
+) -> Result<serde_json::Map<String, serde_json::Value>, JsonError> {
+    let (json, offsets) = decode_entities(raw);
+    let err = match serde_json::from_str::<serde_json::Value>(&json) {
+        Ok(serde_json::Value::Object(object)) => return Ok(object),
+        Ok(_) => return Err(JsonError::NotAnObject),
+        Err(err) => err,
+    };
+    // htmx adds the braces around the object when they are left out
+    let wrapped = !json.starts_with('{');
+    let err = if wrapped {
+        match serde_json::from_str(&format!("{{{json}}}")) {
+            Ok(object) => return Ok(object),
+            Err(err) => err,
+        }
+    } else {
+        err
+    };
+    let span = error_span(&json, &err, wrapped);
+    // The position in the message is relative to the decoded value, and possibly the added braces
+    let message = err.to_string();
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message.as_str(), |(message, _)| message);
+    Err(JsonError::Invalid {
+        span: offsets[span.start]..offsets[span.end],
+        message: message.to_string(),
+    })
+}
+
+/// Returns the names of the values added to requests by `hx-vals` and `hx-vars` on the element at index
+/// `i` and its ancestors, which are merged together
+///
+/// Returns `None` when they can't be known, because they are computed by JavaScript or come from a
+/// template
+fn extra_values(file: &FileData, i: usize) -> Option<Vec<Cow<'_, str>>> {
+    let mut names = Vec::new();
+    for tag in file
+        .ancestors(i)
+        .filter_map(|i| file.dom.nodes().get(i)?.as_tag())
+    {
+        if let Some(vals) = attribute(tag, "hx-vals") {
+            let json = vals.trim();
+            if file.is_templated(vals) || json.starts_with("js:") || json.starts_with("javascript:")
+            {
+                return None;
+            }
+            let object = parse_json_object(json).ok()?;
+            names.extend(object.into_iter().map(|(name, _)| Cow::Owned(name)));
+        }
+        if let Some(vars) = attribute(tag, "hx-vars") {
+            if file.is_templated(vars) {
+                return None;
+            }
+            names.extend(
+                vars.split(',')
+                    .filter_map(|var| Some(Cow::Borrowed(var.split_once(':')?.0.trim()))),
+            );
+        }
+    }
+    Some(names)
+}
+
+/// Returns the names of the parameters submitted by the element at index `i`, which are the fields of its
+/// form, its own value and the values added by `hx-vals` and `hx-vars`
+///
+/// Returns `None` when the element isn't in a form, when it includes other elements with `hx-include`,
+/// or when a field name or value comes from a template or JavaScript, since the parameters can't be
+/// known then
+fn submitted_fields(file: &FileData, i: usize) -> Option<Vec<Cow<'_, str>>> {
+    let form = file.enclosing_form(i)?;
+    let tag = file.dom.nodes().get(i)?.as_tag()?;
+    if file.inherited(i, "hx-include").is_some() {
+        return None;
+    }
+    let fields: Vec<&str> = file
+        .form_fields(form)
+        .into_iter()
+        .chain(attribute(tag, "name"))
+        .collect();
+    if fields.iter().any(|name| file.is_templated(name)) {
+        return None;
+    }
+    Some(
+        fields
+            .into_iter()
+            .map(Cow::Borrowed)
+            .chain(extra_values(file, i)?)
+            .unique()
+            .collect(),
+    )
+}
+
+/// Checks that `hx-params` only names fields that are submitted with the request
+fn check_params(file: &FileData) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, tag) in file.tags() {
+        // An empty value doesn't filter anything
+        let value =
+            attribute(tag, "hx-params").filter(|v| !v.trim().is_empty() && !file.is_templated(v));
+        let Some(value) = value else {
+            continue;
+        };
+        let params = Params::parse(value);
+        if params.names().iter().any(|name| name.is_empty()) {
+            diagnostics.push(diagnostic(
+                file,
+                value,
+                "hx-params: expected `*`, `none`, `not <names>` or a comma separated list of names"
+                    .into(),
+            ));
+            continue;
+        }
+        let Some(fields) = submitted_fields(file, i) else {
+            continue;
+        };
+        diagnostics.extend(
+            params
+                .names()
+                .iter()
+                .filter(|name| !fields.iter().any(|field| field == *name))
+                .map(|name| {
+                    diagnostic(
+                        file,
+                        name,
+                        format!(
+                            "hx-params: `{name}` is not a field of the form, hx-vals or hx-vars"
+                        ),
+                    )
+                }),
+        );
+    }
+    diagnostics
+}
+
+/// Checks the strategy of `hx-sync`, and that a relative selector picks an element to sync with
+///
+/// Plain CSS selectors are checked against the page with the other selector attributes
+fn check_sync(file: &FileData) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, tag) in file.tags() {
+        let Some(value) = attribute(tag, "hx-sync").filter(|v| !file.is_templated(v)) else {
+            continue;
+        };
+        let (selector, strategy) = split_sync(value);
+        // htmx compares strategies exactly, and only splits `queue` from its mode on a single space
+        if let Some(strategy) = strategy.filter(|s| !s.is_empty() && !SYNC_STRATEGIES.contains(s)) {
+            diagnostics.push(Diagnostic {
+                severity: Some(DiagnosticSeverity::ERROR),
+                ..diagnostic(
+                    file,
+                    strategy,
+                    format!(
+                        "hx-sync: unknown strategy `{strategy}`, expected `drop`, `abort`, `replace` or `queue [first|last|all]`"
+                    ),
+                )
+            });
+        }
+        if selector.is_empty() {
+            diagnostics.push(Diagnostic {
+                severity: Some(DiagnosticSeverity::ERROR),
+                ..diagnostic(
+                    file,
+                    value,
+                    "hx-sync: expected a selector for the element to sync with".into(),
+                )
+            });
+            continue;
+        }
+        let parsed = ExtendedSelector::parse(selector);
+        if parsed.absolute().is_none()
+            && parsed
+                .resolve(file, i)
+                .is_some_and(|nodes| nodes.is_empty())
+        {
+            diagnostics.push(diagnostic(
+                file,
+                selector,
+                format!("hx-sync: no element matches `{selector}`"),
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// Checks the values of attributes that only accept a fixed set of values
+fn check_types(file: &FileData) -> Vec<Diagnostic> {
+    file.attributes()
+        .filter(|(_, _, value)| !value.trim().is_empty() && !file.is_templated(value))
+        .filter_map(|(_, key, value)| Some((key, value, VALUE_TYPES.get(key)?)))
+        .flat_map(|(key, value, value_type)| {
+            value_type.invalid(value).into_iter().map(move |part| {
+                diagnostic(
+                    file,
+                    part,
+                    format!("{key}: expected {}, found `{part}`", value_type.expected()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Computes the diagnostics for the values of attributes in `file`
+pub fn check(file: &FileData) -> Vec<Diagnostic> {
+    let mut diagnostics = check_params(file);
+    diagnostics.extend(check_sync(file));
+    diagnostics.extend(check_types(file));
+    diagnostics
+}
+
+/// Returns a completion replacing `range` with `label`
+pub fn completion(label: &str, kind: CompletionItemKind, range: Range) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: label.to_string(),
+        })),
+        ..Default::default()
+    }
+}
+
+/// Returns the bounds of the comma separated item of `value` under `cursor`, without the whitespace
+/// around it
+pub fn list_item(value: &str, cursor: usize) -> (usize, usize) {
+    let start = value[..cursor].rfind(',').map_or(0, |i| i + 1);
+    let end = value[cursor..]
+        .find(',')
+        .map_or(value.len(), |i| cursor + i);
+    let item = &value[start..end];
+    let start = start + (item.len() - item.trim_start().len());
+    (start, start.max(end - (item.len() - item.trim_end().len())))
+}
+
+/// Completes the extended selector between `start` and `end` in `value`, with `this` and the keywords of
+/// relative selectors at the start of it, or with `targets` after a keyword or in place of one
+fn complete_selector(
+    file: &FileData,
+    value: &str,
+    (mut start, end): (usize, usize),
+    cursor: usize,
+    targets: impl Iterator<Item = String>,
+) -> Vec<CompletionItem> {
+    let keyword = RELATIVE_KEYWORDS.iter().find(|keyword| {
+        value[start..cursor]
+            .strip_prefix(**keyword)
+            .is_some_and(|rest| rest.starts_with(char::is_whitespace))
+    });
+    if let Some(keyword) = keyword {
+        start += keyword.len();
+        start += value[start..cursor].len() - value[start..cursor].trim_start().len();
+    }
+    let range = file.range_of(&value[start..end.max(start)]);
+    let keywords = std::iter::once("this".to_string())
+        .chain(
+            RELATIVE_KEYWORDS
+                .iter()
+                .map(|keyword| format!("{keyword} ")),
+        )
+        .filter(|_| keyword.is_none())
+        .map(|label| completion(&label, CompletionItemKind::KEYWORD, range));
+    keywords
+        .chain(
+            targets
+                .unique()
+                .map(|label| completion(&label, CompletionItemKind::REFERENCE, range)),
+        )
+        .collect()
+}
+
+/// Completes the name under the cursor in `hx-params`, or one of the keywords at the start of it
+fn complete_params(file: &FileData, value: &str, cursor: usize) -> Vec<CompletionItem> {
+    let (mut start, end) = list_item(value, cursor);
+    // Keywords can only start the value, and `not` is followed by names
+    let first = !value[..start].contains(',');
+    let negated = first && value[start..cursor].starts_with(Params::NOT);
+    if negated {
+        start += Params::NOT.len();
+        start += value[start..cursor].len() - value[start..cursor].trim_start().len();
+    }
+    let range = file.range_of(&value[start..end.max(start)]);
+    let keywords = [Params::ALL, Params::NONE, Params::NOT]
+        .into_iter()
+        .filter(|_| first && !negated)
+        .map(|label| completion(label, CompletionItemKind::KEYWORD, range));
+    let fields = file
+        .tag_of(value)
+        .and_then(|i| submitted_fields(file, i))
+        .unwrap_or_default();
+    keywords
+        .chain(
+            fields
+                .into_iter()
+                .map(|name| completion(&name, CompletionItemKind::FIELD, range)),
+        )
+        .collect()
+}
+
+/// Completes the selector under the cursor in `hx-include` with the named fields and the forms of `file`,
+/// or with one of the extended selector keywords at the start of it
+fn complete_include(file: &FileData, value: &str, cursor: usize) -> Vec<CompletionItem> {
+    let names = file
+        .tags()
+        .filter_map(|(_, tag)| field_name(tag))
+        .filter(|name| !file.is_templated(name))
+        .map(|name| format!("[name='{name}']"));
+    let ids = file
+        .tags()
+        .filter(|(_, tag)| {
+            tag.name().as_bytes().eq_ignore_ascii_case(b"form") || field_name(tag).is_some()
+        })
+        .filter_map(|(_, tag)| attribute(tag, "id"))
+        .filter(|id| !file.is_templated(id))
+        .map(|id| format!("#{id}"));
+    complete_selector(
+        file,
+        value,
+        list_item(value, cursor),
+        cursor,
+        ids.chain(names),
+    )
+}
+
+/// Completes the selector of `hx-sync` with the extended selector keywords and the forms of `file`, or
+/// the strategy after the colon
+fn complete_sync(file: &FileData, value: &str, cursor: usize) -> Vec<CompletionItem> {
+    if let Some(colon) = value[..cursor].find(':') {
+        let start = colon + 1;
+        let (item_start, item_end) = list_item(&value[start..], cursor - start);
+        let range = file.range_of(&value[start + item_start..start + item_end]);
+        return SYNC_STRATEGIES
+            .iter()
+            .map(|label| completion(label, CompletionItemKind::ENUM_MEMBER, range))
+            .collect();
+    }
+    let selector = &value[..value.find(':').unwrap_or(value.len())];
+    let forms = file
+        .tags()
+        .filter(|(_, tag)| tag.name().as_bytes().eq_ignore_ascii_case(b"form"))
+        .filter_map(|(_, tag)| attribute(tag, "id"))
+        .filter(|id| !file.is_templated(id))
+        .map(|id| format!("#{id}"))
+        .chain(std::iter::once("form".to_string()));
+    complete_selector(file, value, list_item(selector, cursor), cursor, forms)
+}
+
+/// Completes the values of attributes that only accept a fixed set of values, or the attribute name
+/// under the cursor in a list of attributes
+fn complete_type(
+    file: &FileData,
+    value_type: &ValueType,
+    value: &str,
+    cursor: usize,
+) -> Vec<CompletionItem> {
+    let ValueType::Attributes = value_type else {
+        let range = file.range_of(value);
+        return value_type
+            .values()
+            .iter()
+            .map(|label| completion(label, CompletionItemKind::ENUM_MEMBER, range))
+            .collect();
+    };
+    let start = value[..cursor]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + 1);
+    let end = value[cursor..]
+        .find(char::is_whitespace)
+        .map_or(value.len(), |i| cursor + i);
+    let range = file.range_of(&value[start..end]);
+    let all = value[..start].trim().is_empty().then_some("*");
+    all.into_iter()
+        .chain(
+            htmx::ATTRIBUTES
+                .iter()
+                .copied()
+                .filter(|name| !name.ends_with('*')),
+        )
+        .map(|label| completion(label, CompletionItemKind::PROPERTY, range))
+        .collect()
+}
+
+/// Completes the extension name under the cursor in `hx-ext`, or the `ignore:` prefix that stops an
+/// inherited extension from applying
+fn complete_ext(file: &FileData, value: &str, cursor: usize) -> Vec<CompletionItem> {
+    let (mut start, end) = list_item(value, cursor);
+    let ignore = value[start..cursor].starts_with("ignore:");
+    if ignore {
+        start += "ignore:".len();
+    }
+    let range = file.range_of(&value[start..end.max(start)]);
+    let prefix = (!ignore).then(|| completion("ignore:", CompletionItemKind::KEYWORD, range));
+    prefix
+        .into_iter()
+        .chain(
+            extensions::EXTENSIONS
+                .entries()
+                .map(|(name, doc)| CompletionItem {
+                    detail: doc.lines().next().map(String::from),
+                    documentation: Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: (*doc).to_string(),
+                    })),
+                    ..completion(name, CompletionItemKind::MODULE, range)
+                }),
+        )
+        .collect()
+}
+
+/// Completes the value of `key`, which is `value`, at byte offset `off` in `file`
+///
+/// Returns `None` for attributes whose values aren't completed, including those of libraries the
+/// workspace doesn't use
+pub fn completions(
+    file: &FileData,
+    key: &str,
+    value: &str,
+    off: usize,
+    config: &Config,
+) -> Option<Vec<CompletionItem>> {
+    let cursor = off
+        .saturating_sub(str_ptr_offset(&file.data, value))
+        .min(value.len());
+    match key {
+        "hx-params" => Some(complete_params(file, value, cursor)),
+        "hx-include" => Some(complete_include(file, value, cursor)),
+        "hx-sync" => Some(complete_sync(file, value, cursor)),
+        "hx-ext" => Some(complete_ext(file, value, cursor)),
+        "sse-swap" | "sse-close" | "hx-trigger" => sse::completions(file, key, value, cursor),
+        _ if config.uses(Library::Alpine) && alpine::directive(key).is_some() => {
+            alpine::complete_magics(file, value, cursor)
+        }
+        _ if config.uses(Library::Hyperscript) && hyperscript::ATTRIBUTES.contains(&key) => {
+            Some(hyperscript::completions(file, value, cursor))
+        }
+        _ if config.uses(Library::Datastar) && datastar::parse(key).is_some() => {
+            datastar::complete_signals(file, key, value, cursor)
+        }
+        _ => Some(complete_type(file, VALUE_TYPES.get(key)?, value, cursor)),
+    }
+}
+
+/// Describes the part of `value` under byte offset `off` in `file`, the value of `key`
+///
+/// Only the extensions in `hx-ext`, the magic properties in Alpine directives and _hyperscript commands
+/// are described
+pub fn hover(
+    file: &FileData,
+    key: &str,
+    value: &str,
+    off: usize,
+    config: &Config,
+) -> Option<Hover> {
+    let cursor = off
+        .saturating_sub(str_ptr_offset(&file.data, value))
+        .min(value.len());
+    let (name, doc) = if key == "hx-ext" {
+        let (start, end) = list_item(value, cursor);
+        let item = &value[start..end];
+        let name = item.strip_prefix("ignore:").unwrap_or(item).trim();
+        (name, (*extensions::EXTENSIONS.get(name)?).to_string())
+    } else if config.uses(Library::Alpine) && alpine::directive(key).is_some() {
+        let (name, doc) = alpine::magic(value, cursor)?;
+        (name, doc.to_string())
+    } else if config.uses(Library::Hyperscript) && hyperscript::ATTRIBUTES.contains(&key) {
+        hyperscript::describe(value, cursor)?
+    } else {
+        return None;
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: doc,
+        }),
+        range: Some(file.range_of(name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::textstore::TextStore;
+
+    fn messages(diagnostics: Vec<Diagnostic>) -> Vec<String> {
+        diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    /// Completes the value of `key` in `html`, at the cursor marked with `|`
+    fn complete(html: &str, key: &str) -> Vec<String> {
+        let cursor = html.find('|').unwrap();
+        let store = TextStore::with_page(&html.replace('|', ""));
+        let file = store.files.values().next().unwrap();
+        let (_, _, value) = file.attributes().find(|(_, k, _)| *k == key).unwrap();
+        let items = completions(file, key, value, cursor, &Config::default()).unwrap();
+        items.into_iter().map(|item| item.label).collect()
+    }
+
+    #[test]
+    fn parses_params() {
+        assert_eq!(Params::parse(" * "), Params::All);
+        assert_eq!(Params::parse("none"), Params::None);
+        assert_eq!(Params::parse("not a, b"), Params::Not(vec!["a", "b"]));
+        assert_eq!(Params::parse("a,b "), Params::Only(vec!["a", "b"]));
+        assert_eq!(Params::parse("a,,b").names(), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn params_must_be_submitted() {
+        let store = TextStore::with_page(
+            r#"<form><input name="q"><button hx-post="/s" hx-params="q, page, size"></button></form>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        assert_eq!(
+            messages(check_params(file)),
+            [
+                "hx-params: `page` is not a field of the form, hx-vals or hx-vars",
+                "hx-params: `size` is not a field of the form, hx-vals or hx-vars",
+            ]
+        );
+    }
+
+    #[test]
+    fn params_may_come_from_vals_and_vars() {
+        let store = TextStore::with_page(
+            r#"<form hx-vals='"page": 1'><input name="q"><button hx-post="/s" hx-vars="size:10" hx-params="not q, page, size"></button></form>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        assert!(check_params(file).is_empty());
+    }
+
+    #[test]
+    fn params_are_unknown_with_includes_and_scripts() {
+        for attribute in [r##"hx-include="#other""##, r#"hx-vals="js:{page: 1}""#] {
+            let store = TextStore::with_page(&format!(
+                r#"<form {attribute}><button hx-post="/s" hx-params="page"></button></form>"#
+            ));
+            let file = store.files.values().next().unwrap();
+            assert!(check_params(file).is_empty(), "{attribute}");
+        }
+    }
+
+    #[test]
+    fn params_must_not_be_empty() {
+        let store = TextStore::with_page(r#"<form><button hx-params="a,"></button></form>"#);
+        let file = store.files.values().next().unwrap();
+        assert_eq!(check_params(file).len(), 1);
+    }
+
+    #[test]
+    fn list_item_trims_the_item_under_the_cursor() {
+        assert_eq!(list_item("a, b ,c", 4), (3, 4));
+        assert_eq!(list_item("a, b ,c", 0), (0, 1));
+        assert_eq!(list_item("a,  ", 3), (4, 4));
+        assert_eq!(list_item("", 0), (0, 0));
+    }
+
+    #[test]
+    fn completes_params_keywords_only_at_the_start() {
+        let html = r#"<form><input name="q"><button hx-params="|"></button></form>"#;
+        assert_eq!(complete(html, "hx-params"), ["*", "none", "not ", "q"]);
+        let html = r#"<form><input name="q"><button hx-params="not |"></button></form>"#;
+        assert_eq!(complete(html, "hx-params"), ["q"]);
+        let html = r#"<form><input name="q"><button hx-params="q, |"></button></form>"#;
+        assert_eq!(complete(html, "hx-params"), ["q"]);
+    }
+
+    #[test]
+    fn completes_include_keywords_and_targets() {
+        let html = r#"<form id="f"><input name="q"><button hx-include="|"></button></form>"#;
+        assert_eq!(
+            complete(html, "hx-include"),
+            [
+                "this",
+                "closest ",
+                "find ",
+                "next ",
+                "previous ",
+                "#f",
+                "[name='q']"
+            ]
+        );
+        let html =
+            r#"<form id="f"><input name="q"><button hx-include="this, closest |"></button></form>"#;
+        assert_eq!(complete(html, "hx-include"), ["#f", "[name='q']"]);
+        let html = r#"<FORM id="f"><button hx-include="closest |"></button></FORM>"#;
+        assert_eq!(complete(html, "hx-include"), ["#f"]);
+    }
+
+    #[test]
+    fn splits_sync_at_the_first_colon() {
+        assert_eq!(
+            split_sync("closest form:abort"),
+            ("closest form", Some("abort"))
+        );
+        assert_eq!(
+            split_sync(" this : queue  all "),
+            ("this", Some("queue  all"))
+        );
+        assert_eq!(split_sync("#f"), ("#f", None));
+        assert_eq!(split_sync(":drop"), ("", Some("drop")));
+    }
+
+    #[test]
+    fn checks_sync_strategies_exactly() {
+        let store = TextStore::with_page(
+            r#"<form id="f"><button hx-sync="this:queue all"></button><button hx-sync="this:Queue Last"></button><button hx-sync="this:queue  last"></button><button hx-sync=":drop"></button></form>"#,
+        );
+        let file = store.files.values().next().unwrap();
+        let messages = messages(check_sync(file));
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with("hx-sync: unknown strategy `Queue Last`"));
+        assert!(messages[1].starts_with("hx-sync: unknown strategy `queue  last`"));
+        assert_eq!(
+            messages[2],
+            "hx-sync: expected a selector for the element to sync with"
+        );
+    }
+
+    #[test]
+    fn completes_sync_selectors_and_strategies() {
+        let html = r#"<form id="f"><button hx-sync="closest |:abort"></button></form>"#;
+        assert_eq!(complete(html, "hx-sync"), ["#f", "form"]);
+        let html = r#"<form id="f"><button hx-sync="|"></button></form>"#;
+        assert_eq!(complete(html, "hx-sync")[..2], ["this", "closest "]);
+        let html = r#"<form id="f"><button hx-sync="this: q|"></button></form>"#;
+        assert_eq!(complete(html, "hx-sync"), SYNC_STRATEGIES);
+        let html = r#"<Form id="f"><button hx-sync="closest |"></button></Form>"#;
+        assert_eq!(complete(html, "hx-sync"), ["#f", "form"]);
+    }
+
+    #[test]
+    fn value_types_report_invalid_parts() {
+        assert!(ValueType::Boolean.invalid("true").is_empty());
+        assert_eq!(ValueType::Boolean.invalid("yes"), ["yes"]);
+        assert!(ValueType::BooleanOrUrl.invalid("/contacts").is_empty());
+        assert!(VALUE_TYPES["hx-preserve"].invalid("").is_empty());
+        assert!(VALUE_TYPES["hx-preserve"].invalid("1").is_empty());
+        assert_eq!(VALUE_TYPES["hx-preserve"].values(), ["true"]);
+        assert_eq!(VALUE_TYPES["hx-encoding"].invalid("json"), ["json"]);
+        assert!(ValueType::Attributes.invalid(" * ").is_empty());
+        assert_eq!(
+            ValueType::Attributes.invalid("hx-target hx-nope hx-select"),
+            ["hx-nope"]
+        );
+    }
+
+    #[test]
+    fn value_types_describe_expected_values() {
+        assert_eq!(ValueType::Boolean.expected(), "`true` or `false`");
+        assert_eq!(
+            VALUE_TYPES["hx-encoding"].expected(),
+            "`multipart/form-data` or `application/x-www-form-urlencoded`"
+        );
+        assert_eq!(ValueType::Attributes.values(), ["*"]);
+    }
+
+    #[test]
+    fn completes_extension_names_and_ignore() {
+        let labels = complete(r#"<div hx-ext="json-enc, |"></div>"#, "hx-ext");
+        assert_eq!(labels[0], "ignore:");
+        assert_eq!(labels.len(), extensions::EXTENSIONS.len() + 1);
+        assert!(labels.iter().any(|label| label == "response-targets"));
+
+        let labels = complete(r#"<div hx-ext="ignore:|"></div>"#, "hx-ext");
+        assert_eq!(labels.len(), extensions::EXTENSIONS.len());
+        assert!(!labels.iter().any(|label| label == "ignore:"));
+    }
+
+    /// Returns the text the error parsing `json` is reported at
+    fn error_at(json: &str) -> &str {
+        match parse_json_object(json) {
+            Err(JsonError::Invalid { span, .. }) => &json[span],
+            _ => panic!("{json} should be invalid"),
+        }
+    }
+
+    #[test]
+    fn json_errors_point_at_the_offending_character() {
+        assert_eq!(error_at(r#"{"a": 1,, "b": 2}"#), ",");
+        assert_eq!(error_at(r#"{"a": x}"#), "x");
+    }
+
+    #[test]
+    fn json_errors_in_values_without_braces() {
+        assert_eq!(error_at(r#""a": x"#), "x");
+        assert_eq!(error_at("\"a\": 1,\n\"b\": x"), "x");
+        assert_eq!(error_at("\"a\": 1,\n  \"b\" x"), "x");
+    }
+
+    #[test]
+    fn json_errors_on_later_lines() {
+        assert_eq!(error_at("{\n  \"a\": 1,\n  \"b\": é\n}"), "é");
+    }
+
+    #[test]
+    fn json_errors_past_the_end_are_clamped() {
+        assert_eq!(error_at(r#"{"a": 1"#), "1");
+        assert_eq!(error_at(r#""a": [1"#), "");
+    }
+
+    #[test]
+    fn json_entities_are_decoded() {
+        assert!(
+            parse_json_object("{&quot;a&quot;: 1, &#34;b&#34;: &quot;x &amp; y&quot;}").is_ok()
+        );
+        assert!(parse_json_object("&quot;a&quot;: &apos;b&apos;").is_err());
+        assert_eq!(error_at("{&quot;a&quot;: x}"), "x");
+        assert_eq!(error_at("{&quot;a&quot; 1}"), "1");
+        assert_eq!(error_at("{&quot;a&quot;: &quot;é&quot;,}"), "}");
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::{info, warn};
+use lsp_types::{InitializeParams, Uri};
+
+use crate::{
+    library::{LibraryIndex, DEFINITION_SUFFIXES},
+    routes::{RouteIndex, SOURCE_EXTENSIONS},
+    template::RefKind,
+    textstore::{FileData, TextStore},
+};
+
+/// File extensions of the templates that are indexed on startup
+const TEMPLATE_EXTENSIONS: &[&str] = &[
+    "html",
+    "htm",
+    "jinja",
+    "jinja2",
+    "j2",
+    "tera",
+    "gohtml",
+    "gotmpl",
+    "tmpl",
+    "hbs",
+    "handlebars",
+    "mustache",
+    "templ",
+];
+
+/// Directories that never contain templates we care about
+const IGNORED_DIRS: &[&str] = &["node_modules", "target", "venv", "env", "__pycache__"];
+
+/// Converts a `file://` uri to a path
+pub fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if uri.scheme().is_none_or(|s| s.as_str() != "file") {
+        return None;
+    }
+    Some(PathBuf::from(
+        uri.path().as_estr().decode().into_string_lossy().as_ref(),
+    ))
+}
+
+/// Converts an absolute path to a `file://` uri
+pub fn path_to_uri(path: &Path) -> Option<Uri> {
+    let mut uri = String::from("file://");
+    for b in path.to_str()?.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(char::from(b));
+        } else {
+            let _ = write!(uri, "%{b:02X}");
+        }
+    }
+    Uri::from_str(&uri).ok()
+}
+
+/// The root of the project, which is scanned for templates on startup
+pub struct Workspace {
+    pub root: Option<PathBuf>,
+}
+
+impl Workspace {
+    pub fn new(params: &InitializeParams) -> Self {
+        #[allow(deprecated)]
+        let root = params
+            .workspace_folders
+            .as_ref()
+            .and_then(|folders| folders.first())
+            .map(|folder| &folder.uri)
+            .or(params.root_uri.as_ref())
+            .and_then(uri_to_path);
+        Self { root }
+    }
+
+    /// Loads every template in the workspace into `textstore`, the routes registered by backend sources
+    /// into `routes` and the attributes described by definition files into `library`
+    pub fn index(
+        &self,
+        textstore: &mut TextStore,
+        routes: &mut RouteIndex,
+        library: &mut LibraryIndex,
+    ) {
+        let Some(root) = &self.root else {
+            return;
+        };
+        let mut stack = vec![root.clone()];
+        while let Some(dir) = stack.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                warn!("Failed to read directory {}", dir.display());
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if path.is_dir() {
+                    if !name.starts_with('.') && !IGNORED_DIRS.contains(&name.as_ref()) {
+                        stack.push(path);
+                    }
+                    continue;
+                }
+                let extension = path.extension().and_then(|ext| ext.to_str());
+                let is_template = extension.is_some_and(|ext| TEMPLATE_EXTENSIONS.contains(&ext));
+                let is_source = extension.is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext));
+                let is_definition = DEFINITION_SUFFIXES
+                    .iter()
+                    .any(|(suffix, _)| name.ends_with(suffix));
+                if !is_template && !is_source && !is_definition {
+                    continue;
+                }
+                let (Some(uri), Ok(content)) = (path_to_uri(&path), fs::read_to_string(&path))
+                else {
+                    warn!("Failed to index {}", path.display());
+                    continue;
+                };
+                if is_definition {
+                    library.update(&uri, &content);
+                } else if is_source {
+                    routes.update(&uri, &content);
+                } else {
+                    textstore.insert(uri, &content, None);
+                }
+            }
+        }
+        info!("Indexed {} templates", textstore.files.len());
+    }
+}
+
+/// The graph of `{% extends %}` and `{% include %}` references between templates
+pub struct TemplateGraph {
+    /// Maps each template to the templates it references
+    edges: HashMap<Uri, Vec<(RefKind, Uri)>>,
+    /// The path of every template on disk
+    paths: Vec<(Uri, PathBuf)>,
+}
+
+impl TemplateGraph {
+    pub fn new(textstore: &TextStore) -> Self {
+        let paths: Vec<(Uri, PathBuf)> = textstore
+            .files
+            .keys()
+            .filter_map(|uri| Some((uri.clone(), uri_to_path(uri)?)))
+            .collect();
+        let edges = textstore
+            .files
+            .iter()
+            .map(|(uri, file)| (uri.clone(), Self::resolve_references(uri, file, &paths)))
+            .collect();
+        Self { edges, paths }
+    }
+
+    /// Finds the template called `name`, preferring one next to `from`
+    ///
+    /// Template names are relative to a loader directory which we don't know about, so a name resolves to
+    /// a sibling of `from` if there is one, and to any template whose path ends with that name otherwise
+    pub fn resolve<'a>(&'a self, name: &str, from: Option<&Uri>) -> Option<&'a Uri> {
+        Self::resolve_in(name, from, &self.paths)
+    }
+
+    fn resolve_in<'a>(
+        name: &str,
+        from: Option<&Uri>,
+        paths: &'a [(Uri, PathBuf)],
+    ) -> Option<&'a Uri> {
+        let sibling = from
+            .and_then(uri_to_path)
+            .and_then(|p| Some(p.parent()?.join(name)));
+        paths
+            .iter()
+            .find(|(_, path)| Some(path) == sibling.as_ref())
+            .or_else(|| paths.iter().find(|(_, path)| path.ends_with(name)))
+            // Handlebars partials are referenced without their extension
+            .or_else(|| {
+                paths
+                    .iter()
+                    .find(|(_, path)| path.with_extension("").ends_with(name))
+            })
+            .map(|(uri, _)| uri)
+    }
+
+    /// Finds the files referenced by `file`
+    ///
+    /// Go templates are referenced by their `{{ define }}` name, which usually matches the file name
+    fn resolve_references(
+        uri: &Uri,
+        file: &FileData,
+        paths: &[(Uri, PathBuf)],
+    ) -> Vec<(RefKind, Uri)> {
+        file.references
+            .iter()
+            .filter_map(|r| {
+                let Some(target) = Self::resolve_in(&r.name, Some(uri), paths) else {
+                    info!("Could not resolve template {:?}", r.name);
+                    return None;
+                };
+                Some((r.kind, target.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns every template whose contents may end up in the same page as `uri`, including itself
+    ///
+    /// This is everything `uri` extends or includes, and, since `uri` may itself be included in or
+    /// extended by other templates, everything in the pages built from those templates
+    pub fn composed(&self, uri: &Uri) -> HashSet<&Uri> {
+        let Some((uri, _)) = self.edges.get_key_value(uri) else {
+            return HashSet::new();
+        };
+        // Templates that (transitively) pull in `uri`
+        let mut roots = HashSet::new();
+        let mut stack = vec![uri];
+        while let Some(curr) = stack.pop() {
+            if roots.insert(curr) {
+                stack.extend(
+                    self.edges
+                        .iter()
+                        .filter(|(_, refs)| refs.iter().any(|(_, target)| target == curr))
+                        .map(|(referrer, _)| referrer),
+                );
+            }
+        }
+        // Everything those templates pull in
+        roots
+            .into_iter()
+            .flat_map(|root| self.rendered(root))
+            .collect()
+    }
+
+    /// Returns every template rendered as part of `uri`, including itself
+    ///
+    /// Unlike [`Self::composed`], this doesn't include the templates `uri` is itself part of, which is
+    /// what a response rendered from `uri` consists of
+    pub fn rendered(&self, uri: &Uri) -> HashSet<&Uri> {
+        let Some((uri, _)) = self.edges.get_key_value(uri) else {
+            return HashSet::new();
+        };
+        let mut seen = HashSet::new();
+        let mut stack = vec![uri];
+        while let Some(curr) = stack.pop() {
+            if seen.insert(curr) {
+                stack.extend(self.edges.get(curr).into_iter().flatten().map(|(_, t)| t));
+            }
+        }
+        seen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn uri(path: &str) -> Uri {
+        Uri::from_str(&format!("file:///app/templates/{path}")).unwrap()
+    }
+
+    fn store(files: &[(&str, &str)]) -> TextStore {
+        TextStore::with_files(files.iter().map(|(path, text)| (uri(path), *text)))
+    }
+
+    fn sorted(uris: HashSet<&Uri>) -> Vec<&str> {
+        let mut paths: Vec<&str> = uris
+            .into_iter()
+            .map(|uri| &uri.as_str()["file:///app/templates/".len()..])
+            .collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    #[test]
+    fn resolves_siblings_before_other_paths() {
+        let store = store(&[("a/row.html", ""), ("b/row.html", ""), ("b/page.html", "")]);
+        let graph = TemplateGraph::new(&store);
+        assert_eq!(
+            graph.resolve("row.html", Some(&uri("b/page.html"))),
+            Some(&uri("b/row.html"))
+        );
+        assert_eq!(graph.resolve("a/row.html", None), Some(&uri("a/row.html")));
+        assert_eq!(graph.resolve("b/row", None), Some(&uri("b/row.html")));
+        assert_eq!(graph.resolve("missing.html", None), None);
+    }
+
+    #[test]
+    fn composes_pages_from_extends_and_includes() {
+        let store = store(&[
+            (
+                "layout.html",
+                "<main id=\"main\">{% block content %}{% endblock %}</main>",
+            ),
+            (
+                "index.html",
+                "{% extends 'layout.html' %}{% include 'rows.html' %}",
+            ),
+            ("rows.html", "<tr></tr>"),
+            ("other.html", "<p></p>"),
+        ]);
+        let graph = TemplateGraph::new(&store);
+        assert_eq!(
+            sorted(graph.rendered(&uri("index.html"))),
+            ["index.html", "layout.html", "rows.html"]
+        );
+        assert_eq!(sorted(graph.rendered(&uri("rows.html"))), ["rows.html"]);
+        assert_eq!(
+            sorted(graph.composed(&uri("rows.html"))),
+            ["index.html", "layout.html", "rows.html"]
+        );
+        assert_eq!(sorted(graph.composed(&uri("other.html"))), ["other.html"]);
+    }
+}